@@ -0,0 +1,91 @@
+use anyhow::Result;
+use gar_crawl::crawler::*;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+// Compare crawl throughput with one parsing thread against one per CPU
+// cargo run --release --example parse_bench
+//
+// 1111 pages of about 40 KB on a 1 CPU machine, three runs each, pages/s:
+//                          parse_workers 1      parse_workers 2
+// before the parse pool    658, 695, 732        495, 712, 717   (setting ignored)
+// parse pool               692, 703, 599        764, 745, 752
+// with one CPU the pool only overlaps parsing with fetching,
+// runs on more CPUs are still to be measured
+
+const FANOUT: usize = 10;
+const PARAGRAPHS: usize = 400;
+
+/// A large page linking to its FANOUT children
+fn page(n: usize) -> String {
+    let mut html = String::from("<html><body>");
+    for i in 0..PARAGRAPHS {
+        html.push_str(&format!(
+            "<div class=\"post\"><p>Paragraph {i} of page {n}, <b>with</b> <i>some</i> markup.</p></div>"
+        ));
+    }
+    for k in 1..=FANOUT {
+        html.push_str(&format!("<a href=\"/p/{}\">child</a>", n * FANOUT + k));
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// Serve generated pages over plain HTTP
+async fn serve(listener: TcpListener) {
+    loop {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 4096];
+            let len = stream.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..len]);
+            let n = request
+                .split_whitespace()
+                .nth(1)
+                .and_then(|path| path.strip_prefix("/p/"))
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            let body = page(n);
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(body.as_bytes()).await;
+        });
+    }
+}
+
+async fn run(url: &str, parse_workers: usize) -> Result<()> {
+    let mut pages = 0;
+    let mut posts = 0;
+    let start = Instant::now();
+    Crawler::builder()
+        .add_default_propagators()
+        .add_handler("div.post > p", |_| posts += 1)
+        .on_page(|_| pages += 1)
+        .parse_workers(parse_workers)
+        .depth(3)
+        .build()?
+        .crawl(url)
+        .await?;
+    let secs = start.elapsed().as_secs_f64();
+    println!(
+        "parse_workers {parse_workers:>3}: {pages} pages in {secs:.2}s, {:.1} pages/s ({posts} posts)",
+        pages as f64 / secs
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/p/0", listener.local_addr()?);
+    tokio::spawn(serve(listener));
+
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    run(&url, 1).await?;
+    run(&url, cpus.max(2)).await?;
+    Ok(())
+}
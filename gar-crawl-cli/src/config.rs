@@ -0,0 +1,312 @@
+use crate::{Cli, Command, HttpOptions, ScopeArgs};
+use anyhow::{bail, Context, Result};
+use clap::{ArgMatches, ValueSource};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Set each `field` the config has and the command line doesn't,
+/// `field as Some` for options that are optional on the command line
+macro_rules! merge {
+    ($matches:expr, $config:expr => $args:expr, $($field:ident $(as $wrap:ident)?),* $(,)?) => {
+        $(
+            if let Some(value) = $config.$field {
+                let id = stringify!($field).replace('_', "-");
+                if $matches.value_source(id.as_str()) != Some(ValueSource::CommandLine) {
+                    $args.$field = $($wrap)?(value);
+                }
+            }
+        )*
+    };
+}
+
+/// A TOML config file, keys are long option names with `_` for `-`
+/// Top level keys are options for every command, each command reads its own table
+/// `[scope]` holds the crawl options shared by `crawl` and `sitemap`
+/// A table may also set the options of the tables above it, the more specific table wins
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    #[serde(flatten)]
+    http: HttpConfig,
+    scope: Table<ScopeConfig>,
+    crawl: Table<CrawlConfig, ScopeConfig>,
+    fuzz: Table<FuzzConfig>,
+    links: Table<LinksConfig>,
+    sitemap: Table<SitemapConfig, ScopeConfig>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+/// A table's own options, the options every command has, the crawl options of a command
+/// that crawls, and any key left over
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Table<T, S = ()> {
+    #[serde(flatten)]
+    http: HttpConfig,
+    #[serde(flatten)]
+    scope: S,
+    #[serde(flatten)]
+    own: T,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HttpConfig {
+    workers: Option<usize>,
+    timeout: Option<u64>,
+    verbose: Option<u8>,
+    quiet: Option<bool>,
+    header: Option<Vec<String>>,
+    user_agent: Option<String>,
+    cookie: Option<Vec<String>>,
+    cookies: Option<String>,
+    proxy: Option<Vec<String>>,
+    profile: Option<String>,
+    profile_rotation: Option<String>,
+    insecure: Option<bool>,
+    ca_cert: Option<Vec<String>>,
+    pin_roots: Option<bool>,
+    client_cert: Option<String>,
+    client_key: Option<String>,
+    client_cert_password: Option<String>,
+    min_tls: Option<String>,
+    output: Option<String>,
+    no_progress: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ScopeConfig {
+    url: Option<String>,
+    depth: Option<usize>,
+    revisit: Option<bool>,
+    confine: Option<bool>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    max_pages: Option<usize>,
+    respect_robots: Option<bool>,
+    parallel: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct CrawlConfig {
+    params: Option<bool>,
+    graph: Option<String>,
+    check_links: Option<bool>,
+    report: Option<String>,
+    format: Option<String>,
+}
+
+/// `url`, `wordlist`, `request` and `data` are checked together, so only come from the command line
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct FuzzConfig {
+    scheme: Option<String>,
+    marker: Option<String>,
+    hide_status: Option<Vec<u16>>,
+    format: Option<String>,
+}
+
+/// `url` is required on the command line
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LinksConfig {
+    js: Option<bool>,
+    format: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SitemapConfig {
+    dir: Option<String>,
+    base: Option<String>,
+}
+
+impl Config {
+    pub fn read(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        Self::parse(&text).with_context(|| format!("parsing {path}"))
+    }
+
+    /// Parse a config, failing on keys that aren't options of their table
+    fn parse(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        let tables = [
+            ("", &config.unknown),
+            ("scope.", &config.scope.unknown),
+            ("crawl.", &config.crawl.unknown),
+            ("fuzz.", &config.fuzz.unknown),
+            ("links.", &config.links.unknown),
+            ("sitemap.", &config.sitemap.unknown),
+        ];
+        for (table, unknown) in tables {
+            if let Some(key) = unknown.keys().next() {
+                bail!("unknown key: {table}{key}");
+            }
+        }
+        Ok(config)
+    }
+
+    /// Fill in options of the command being run that weren't given on the command line
+    pub fn apply(self, cli: &mut Cli, matches: &ArgMatches) {
+        let Some((_, sub)) = matches.subcommand() else {
+            return;
+        };
+        // global options given after the command only show up in its matches
+        let http = &mut cli.http;
+        self.http.apply(sub, http);
+
+        // each layer overwrites the one before it
+        match &mut cli.command {
+            Command::Crawl(args) => {
+                self.scope.http.apply(sub, http);
+                self.crawl.http.apply(sub, http);
+                self.scope.own.apply(sub, &mut args.scope);
+                self.crawl.scope.apply(sub, &mut args.scope);
+                merge!(sub, self.crawl.own => args, params, graph as Some, check_links, report, format);
+            }
+            Command::Fuzz(args) => {
+                self.fuzz.http.apply(sub, http);
+                merge!(sub, self.fuzz.own => args, scheme, marker, hide_status, format);
+            }
+            Command::Links(args) => {
+                self.links.http.apply(sub, http);
+                merge!(sub, self.links.own => args, js, format);
+            }
+            Command::Sitemap(args) => {
+                self.scope.http.apply(sub, http);
+                self.sitemap.http.apply(sub, http);
+                self.scope.own.apply(sub, &mut args.scope);
+                self.sitemap.scope.apply(sub, &mut args.scope);
+                merge!(sub, self.sitemap.own => args, dir as Some, base as Some);
+            }
+        }
+    }
+}
+
+impl HttpConfig {
+    fn apply(self, matches: &ArgMatches, http: &mut HttpOptions) {
+        merge!(matches, self => http,
+            workers, timeout, verbose, quiet, header, user_agent as Some, cookie,
+            cookies as Some, proxy, profile as Some, profile_rotation, insecure, ca_cert, pin_roots,
+            client_cert as Some,
+            client_key as Some, client_cert_password as Some, min_tls as Some, output as Some,
+            no_progress,
+        );
+    }
+}
+
+impl ScopeConfig {
+    fn apply(self, matches: &ArgMatches, args: &mut ScopeArgs) {
+        merge!(matches, self => args,
+            url as Some, depth, revisit, confine, include, exclude, max_pages as Some,
+            respect_robots, parallel,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    /// Parse `args` and apply the config `text` to them
+    fn cli(text: &str, args: &[&str]) -> Cli {
+        let matches = Cli::command().get_matches_from(args);
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        Config::parse(text).unwrap().apply(&mut cli, &matches);
+        cli
+    }
+
+    #[test]
+    fn precedence_test() {
+        let text = r#"
+            timeout = 1
+            workers = 1
+            user_agent = "top"
+
+            [scope]
+            timeout = 2
+            workers = 2
+            depth = 5
+            max_pages = 10
+
+            [crawl]
+            timeout = 3
+            depth = 6
+            format = "csv"
+
+            [fuzz]
+            marker = "HERE"
+        "#;
+
+        let crawl = cli(text, &["gar-crawl-cli", "crawl", "-u", "x"]);
+        assert_eq!(crawl.http.timeout, 3);
+        assert_eq!(crawl.http.workers, 2);
+        assert_eq!(crawl.http.user_agent.as_deref(), Some("top"));
+        let Command::Crawl(args) = &crawl.command else {
+            unreachable!()
+        };
+        assert_eq!(args.scope.depth, 6);
+        assert_eq!(args.scope.max_pages, Some(10));
+        assert_eq!(args.format, "csv");
+
+        // the command line wins, before or after the command
+        let args = [
+            "gar-crawl-cli",
+            "--timeout",
+            "9",
+            "crawl",
+            "-d",
+            "1",
+            "-u",
+            "x",
+        ];
+        let crawl = cli(text, &args);
+        assert_eq!(crawl.http.timeout, 9);
+        let Command::Crawl(args) = &crawl.command else {
+            unreachable!()
+        };
+        assert_eq!(args.scope.depth, 1);
+        let args = ["gar-crawl-cli", "crawl", "-u", "x", "--workers", "7"];
+        assert_eq!(cli(text, &args).http.workers, 7);
+
+        // [scope] only applies to the commands that crawl
+        let args = ["gar-crawl-cli", "fuzz", "-u", "x/HERE", "-l", "words"];
+        let fuzz = cli(text, &args);
+        assert_eq!(fuzz.http.timeout, 1);
+        assert_eq!(fuzz.http.workers, 1);
+        let Command::Fuzz(args) = &fuzz.command else {
+            unreachable!()
+        };
+        assert_eq!(args.marker, "HERE");
+    }
+
+    #[test]
+    fn unknown_key_test() {
+        // the README example
+        let readme = include_str!("../README.md");
+        let example = readme.split("```toml\n").nth(1).unwrap();
+        Config::parse(example.split("```").next().unwrap()).unwrap();
+        assert!(Config::parse("timeout = 1\n[sitemap]\ndepth = 2\ndir = \"out\"").is_ok());
+
+        let error = |text: &str| Config::parse(text).err().unwrap().to_string();
+        assert_eq!(error("timout = 1"), "unknown key: timout");
+        assert_eq!(
+            error("[scope]\nformat = \"csv\""),
+            "unknown key: scope.format"
+        );
+        assert_eq!(error("[crawl]\ndir = \"out\""), "unknown key: crawl.dir");
+        assert_eq!(error("[fuzz]\ndepth = 2"), "unknown key: fuzz.depth");
+        assert_eq!(
+            error("[links]\nmax_pages = 2"),
+            "unknown key: links.max_pages"
+        );
+        assert!(error("[bogus]\nx = 1").contains("bogus"));
+        assert!(Config::parse("timeout = \"soon\"").is_err());
+    }
+}
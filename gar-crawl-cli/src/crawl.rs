@@ -0,0 +1,227 @@
+use crate::{crawl_with_progress, each_url, write_line, HttpOptions, Output, ScopeArgs};
+use anyhow::Result;
+use clap::Args;
+use gar_crawl::{absolute_url, auxiliary::csv_field, crawler::*};
+use indicatif::MultiProgress;
+use reqwest::header::CONTENT_TYPE;
+use scraper::Selector;
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::Write;
+
+/// Columns of --format csv, in the order of `page_record`
+const CSV_HEADER: &str = "url,status,depth,referrer,content_type,title";
+
+#[derive(Args)]
+pub struct CrawlArgs {
+    #[clap(flatten)]
+    pub scope: ScopeArgs,
+
+    /// Print discovered endpoints with their params as JSON
+    #[clap(short, long)]
+    pub params: bool,
+
+    /// Write the link graph to a file, format by extension: jsonl, csv, graphml or dot
+    #[clap(long)]
+    pub graph: Option<String>,
+
+    /// Check every link instead of printing them, exits with 1 if any are broken
+    #[clap(long)]
+    pub check_links: bool,
+
+    /// Broken link report format: human, json or junit
+    #[clap(default_value = "human", long)]
+    pub report: String,
+
+    /// Output format: plain prints every discovered url, jsonl and csv a record per crawled page
+    #[clap(default_value = "plain", short, long)]
+    pub format: String,
+}
+
+/// Crawl every start url, returns false if a crawl failed or found broken links
+pub async fn run(
+    http: &HttpOptions,
+    args: &CrawlArgs,
+    progress: &MultiProgress,
+    output: &Output,
+) -> Result<bool> {
+    match args.format.as_str() {
+        "plain" | "jsonl" => (),
+        "csv" => writeln!(output.lock().unwrap(), "{CSV_HEADER}")?,
+        other => anyhow::bail!("unknown format: {other}"),
+    }
+    // fail on a bad format before crawling, not after
+    if let Some(path) = &args.graph {
+        LinkGraph::default().export(graph_format(path))?;
+    }
+    if args.check_links {
+        LinkCheck::default().export(&args.report)?;
+    }
+
+    let results = each_url(args.scope.urls(), args.scope.parallel, progress, |url| {
+        crawl(http, args, url, progress, output)
+    })
+    .await;
+    Ok(results.iter().all(|result| matches!(result, Ok(false))))
+}
+
+/// Crawl `url` and print what was found, returns whether any links are broken
+async fn crawl(
+    http: &HttpOptions,
+    args: &CrawlArgs,
+    url: String,
+    progress: &MultiProgress,
+    output: &Output,
+) -> Result<bool> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let print_links = !args.check_links && args.format == "plain";
+    let print_pages = !args.check_links && args.format != "plain";
+
+    let builder = http
+        .crawler(&url, progress)?
+        .add_default_propagators()
+        .on_page(|page_args| {
+            if print_pages {
+                let record = page_record(page_args.page, &args.format);
+                write_line(output, progress, &record);
+            }
+        })
+        .add_handler("*[href]", |args| {
+            if let Some(href) = args.element.unwrap().value().attr("href") {
+                if let Ok(abs_url) = absolute_url(&args.page.url, href) {
+                    if print_links && seen.insert(abs_url.to_string()) {
+                        write_line(output, progress, abs_url.as_str());
+                    }
+                }
+            }
+        })
+        .inventory(args.params)
+        .link_graph(args.graph.is_some())
+        .link_check(args.check_links);
+    let mut crawler = args.scope.apply(builder, &url).build()?;
+
+    crawl_with_progress(&mut crawler, &url, http, progress).await?;
+
+    if let Some(inventory) = crawler.inventory() {
+        write_line(output, progress, &inventory.to_json()?);
+    }
+
+    if let (Some(graph), Some(path)) = (crawler.link_graph(), &args.graph) {
+        std::fs::write(path, graph.export(graph_format(path))?)?;
+    }
+
+    if let Some(check) = crawler.link_check() {
+        write_line(output, progress, check.export(&args.report)?.trim_end());
+        return Ok(check.broken().next().is_some());
+    }
+
+    Ok(false)
+}
+
+/// Link graph format of a file, by extension
+fn graph_format(path: &str) -> &str {
+    path.rsplit('.').next().unwrap_or_default()
+}
+
+/// A jsonl or csv record for a crawled page
+fn page_record(page: &Page, format: &str) -> String {
+    let content_type = page
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let title = page
+        .doc
+        .select(&Selector::parse("title").unwrap())
+        .next()
+        .map(|title| title.text().collect::<String>().trim().to_string());
+    let referrer = page.referrer.as_ref().map(|url| url.as_str());
+
+    match format {
+        "jsonl" => json!({
+            "url": page.url.as_str(),
+            "status": page.status.as_u16(),
+            "depth": page.depth,
+            "referrer": referrer,
+            "content_type": content_type,
+            "title": title,
+        })
+        .to_string(),
+        _ => [
+            page.url.as_str(),
+            page.status.as_str(),
+            &page.depth.to_string(),
+            referrer.unwrap_or_default(),
+            content_type.unwrap_or_default(),
+            title.as_deref().unwrap_or_default(),
+        ]
+        .map(csv_field)
+        .join(","),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{StatusCode, Url};
+
+    fn page(title: &str) -> Page {
+        let url = Url::parse("https://example.org/a?b=1,2").unwrap();
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "text/html; charset=utf-8".parse().unwrap());
+        Page {
+            url: url.clone(),
+            final_url: url,
+            redirects: vec![],
+            status: StatusCode::NOT_FOUND,
+            headers,
+            text: String::new(),
+            doc: scraper::Html::parse_document(&format!("<title> {title} </title>")),
+            depth: 2,
+            referrer: Some(Url::parse("https://example.org/").unwrap()),
+            robots: RobotsDirectives::default(),
+        }
+    }
+
+    #[test]
+    fn csv_test() {
+        let record = page_record(&page("Say \"hi\", then go"), "csv");
+        assert_eq!(
+            record,
+            "\"https://example.org/a?b=1,2\",404,2,https://example.org/,text/html; charset=utf-8,\"Say \"\"hi\"\", then go\""
+        );
+        assert_eq!(CSV_HEADER.split(',').count(), 6);
+
+        // no referrer, content type or title leaves the columns empty
+        let mut page = page("");
+        page.referrer = None;
+        page.headers.clear();
+        page.doc = scraper::Html::parse_document("");
+        assert_eq!(
+            page_record(&page, "csv"),
+            "\"https://example.org/a?b=1,2\",404,2,,,"
+        );
+    }
+
+    #[test]
+    fn jsonl_test() {
+        let record: serde_json::Value =
+            serde_json::from_str(&page_record(&page("Home"), "jsonl")).unwrap();
+        assert_eq!(
+            record,
+            json!({
+                "url": "https://example.org/a?b=1,2",
+                "status": 404,
+                "depth": 2,
+                "referrer": "https://example.org/",
+                "content_type": "text/html; charset=utf-8",
+                "title": "Home",
+            })
+        );
+        // the record is one line, keyed like the csv columns
+        assert!(!page_record(&page("a\nb"), "jsonl").contains('\n'));
+        let keys: Vec<&String> = record.as_object().unwrap().keys().collect();
+        let mut columns: Vec<&str> = CSV_HEADER.split(',').collect();
+        columns.sort();
+        assert_eq!(keys, columns);
+    }
+}
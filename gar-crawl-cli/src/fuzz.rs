@@ -0,0 +1,136 @@
+use crate::{write_line, HttpOptions, Output};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use gar_crawl::fuzzer::*;
+use indicatif::{HumanBytes, MultiProgress};
+use reqwest::Url;
+use serde_json::json;
+
+#[derive(Args)]
+pub struct FuzzArgs {
+    /// Url to fuzz, the marker is replaced with each word
+    #[clap(short, long, required_unless_present = "request")]
+    pub url: Option<String>,
+
+    /// File with one word per line
+    #[clap(short = 'l', long)]
+    pub wordlist: String,
+
+    /// POST this body, the marker is replaced with each word
+    /// Sent as application/x-www-form-urlencoded unless -H sets a Content-Type
+    #[clap(long)]
+    pub data: Option<String>,
+
+    /// Raw HTTP request to fuzz instead of --url, such as one saved from Burp
+    #[clap(long, conflicts_with_all = &["url", "data"])]
+    pub request: Option<String>,
+
+    /// Scheme for --request
+    #[clap(default_value = "https", long)]
+    pub scheme: String,
+
+    /// Text to replace with each word
+    #[clap(default_value = "FUZZ", long)]
+    pub marker: String,
+
+    /// Don't print responses with these statuses ( comma separated )
+    #[clap(default_value = "404", long, use_value_delimiter = true)]
+    pub hide_status: Vec<u16>,
+
+    /// Output format: plain or jsonl
+    #[clap(default_value = "plain", short, long)]
+    pub format: String,
+}
+
+/// Send a request for every word, returns false if any failed
+pub async fn run(
+    http: &HttpOptions,
+    args: &FuzzArgs,
+    progress: &MultiProgress,
+    output: &Output,
+) -> Result<bool> {
+    if !["plain", "jsonl"].contains(&args.format.as_str()) {
+        bail!("unknown format: {}", args.format);
+    }
+    let words: Vec<String> = std::fs::read_to_string(&args.wordlist)?
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty() && !word.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+
+    let mut fuzzer = FuzzerBuilder {
+        client_builder: http.client()?,
+        ..FuzzerBuilder::new()
+    }
+    .workers(http.workers)
+    .add_handler(|res| {
+        let status = res.response.status().as_u16();
+        if args.hide_status.contains(&status) {
+            return;
+        }
+        let (method, url) = (res.request.method(), res.request.url());
+        let length = res.response.content_length();
+        let line = match args.format.as_str() {
+            "jsonl" => json!({
+                "url": url.as_str(),
+                "method": method.as_str(),
+                "status": status,
+                "length": length,
+            })
+            .to_string(),
+            _ => {
+                let size = length.map_or("-".to_string(), |n| HumanBytes(n).to_string());
+                format!("{status} {size} {method} {url}")
+            }
+        };
+        write_line(output, progress, &line);
+    })
+    .build()?;
+
+    let errors = match (&args.request, &args.url) {
+        (Some(path), _) => {
+            let template = RequestTemplate::from_file(path)?
+                .scheme(&args.scheme)
+                .marker(&args.marker);
+            fuzzer.fuzz_template(&template, &mut words.iter()).await?
+        }
+        (None, Some(url)) => {
+            let marked = url.contains(&args.marker)
+                || args
+                    .data
+                    .as_ref()
+                    .is_some_and(|data| data.contains(&args.marker));
+            if !marked {
+                bail!("{} not found in the url or data", args.marker);
+            }
+            // bodies are sent as a form unless -H says otherwise
+            let form = !http
+                .headers()?
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("content-type"));
+            let mut requests = words.iter().map(|word| {
+                let target = url.replace(&args.marker, word);
+                let url = Url::parse(&target).with_context(|| format!("invalid url: {target}"))?;
+                let Some(data) = &args.data else {
+                    return Ok(FuzzRequest::get(url));
+                };
+                let request = FuzzRequest::post(url, &data.replace(&args.marker, word));
+                Ok(if form {
+                    request.header("Content-Type", "application/x-www-form-urlencoded")
+                } else {
+                    request
+                })
+            });
+            fuzzer.try_fuzz(&mut requests).await?
+        }
+        (None, None) => bail!("--url or --request is required"),
+    };
+
+    if !http.quiet {
+        for error in errors.iter() {
+            progress.suspend(|| eprintln!("error {error}"));
+        }
+    }
+    Ok(errors.is_empty())
+}
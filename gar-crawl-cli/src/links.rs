@@ -0,0 +1,57 @@
+use crate::{crawl_with_progress, write_line, HttpOptions, Output};
+use anyhow::Result;
+use clap::Args;
+use gar_crawl::crawler::LinkGraph;
+use indicatif::MultiProgress;
+use std::collections::BTreeSet;
+
+#[derive(Args)]
+pub struct LinksArgs {
+    /// Page to read links from
+    #[clap(short, long)]
+    pub url: String,
+
+    /// Also find urls in scripts and inline JavaScript
+    #[clap(long)]
+    pub js: bool,
+
+    /// Output format: plain prints each url once, jsonl, csv, graphml and dot print every link
+    /// with the element and attribute it came from
+    #[clap(default_value = "plain", short, long)]
+    pub format: String,
+}
+
+/// Fetch one page and print its links
+pub async fn run(
+    http: &HttpOptions,
+    args: &LinksArgs,
+    progress: &MultiProgress,
+    output: &Output,
+) -> Result<bool> {
+    if args.format != "plain" {
+        LinkGraph::default().export(&args.format)?;
+    }
+    let mut builder = http
+        .crawler(&args.url, progress)?
+        .add_default_propagators()
+        .depth(0)
+        .link_graph(true);
+    if args.js {
+        builder = builder.add_js_propagators();
+    }
+    let mut crawler = builder.build()?;
+    crawl_with_progress(&mut crawler, &args.url, http, progress).await?;
+
+    let graph = crawler.link_graph().unwrap();
+    let text = match args.format.as_str() {
+        "plain" => {
+            let targets: BTreeSet<&str> = graph.edges.iter().map(|e| e.target.as_str()).collect();
+            targets.into_iter().collect::<Vec<_>>().join("\n")
+        }
+        format => graph.export(format)?,
+    };
+    if !text.is_empty() {
+        write_line(output, progress, text.trim_end());
+    }
+    Ok(crawler.stats().snapshot().failed == 0)
+}
@@ -1,89 +1,553 @@
+mod config;
+mod crawl;
+mod fuzz;
+mod links;
+mod sitemap;
+
 use anyhow::Result;
-use clap::Parser;
-use futures::future::join_all;
-use gar_crawl::*;
-use std::{collections::HashSet, sync::Arc};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use futures::stream::{self, StreamExt};
+use gar_crawl::{
+    auxiliary::load_certificate, cookies::CookieJar, crawler::*, profile::*, proxy::ProxyConfig,
+};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use reqwest::{tls::Version, Client, ClientBuilder, Identity};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufWriter, Write};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// User-Agent sent without --profile, --user-agent or a -H User-Agent
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/104.0.5112.79 Safari/537.36";
+
+/// Where results are written, shared by every crawl
+pub type Output = Arc<Mutex<Box<dyn Write + Send>>>;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
-struct Arguments {
-    /// Start url ( will read lines from stdin if not provided as a flag )
-    #[clap(short, long)]
-    url: Option<String>,
+#[clap(args_override_self = true)]
+struct Cli {
+    #[clap(flatten)]
+    http: HttpOptions,
 
-    /// Crawl depth
-    #[clap(default_value_t = 2, short, long)]
-    depth: usize,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Crawl and print discovered urls
+    Crawl(crawl::CrawlArgs),
+    /// Fuzz directories or parameters with a wordlist
+    Fuzz(fuzz::FuzzArgs),
+    /// Print the links on one page
+    Links(links::LinksArgs),
+    /// Crawl and write a sitemap.xml
+    Sitemap(sitemap::SitemapArgs),
+}
 
+// Options shared by every subcommand
+#[derive(Args, Clone)]
+pub struct HttpOptions {
     /// Concurrency limit
-    #[clap(default_value_t = 40, short, long)]
-    workers: usize,
+    #[clap(default_value_t = 40, short, long, global = true)]
+    pub workers: usize,
 
     /// Request timeout ( seconds )
-    #[clap(default_value_t = 10, short, long)]
-    timeout: u64,
+    #[clap(default_value_t = 10, short, long, global = true)]
+    pub timeout: u64,
 
-    /// Revisit urls
+    /// Print each page with its status, depth, size and timing, -vv adds requests and skips
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only print results and fatal errors
+    #[clap(short, long, conflicts_with = "verbose", global = true)]
+    pub quiet: bool,
+
+    /// Extra request header, "Name: value" ( repeatable )
+    #[clap(short = 'H', long, global = true)]
+    pub header: Vec<String>,
+
+    /// User-Agent header, overrides the profile
+    #[clap(long, global = true)]
+    pub user_agent: Option<String>,
+
+    /// Cookie to send, "name=value" ( repeatable )
+    #[clap(long, global = true)]
+    pub cookie: Vec<String>,
+
+    /// Netscape cookies.txt file to load cookies from and save them back to
+    #[clap(long, global = true)]
+    pub cookies: Option<String>,
+
+    /// Proxy url, http, https or socks5 ( repeatable, crawls rotate between them )
+    #[clap(long, global = true)]
+    pub proxy: Vec<String>,
+
+    /// Browser header profile: chrome, firefox, safari, rotate or bot
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Switch rotated profiles per host or per page requested
+    #[clap(default_value = "host", long, value_parser = ["host", "page"], global = true)]
+    pub profile_rotation: String,
+
+    /// Accept invalid and self-signed certificates
+    #[clap(short = 'k', long, global = true)]
+    pub insecure: bool,
+
+    /// Extra root certificate to trust, PEM or DER ( repeatable )
+    #[clap(long, global = true)]
+    pub ca_cert: Vec<String>,
+
+    /// Only trust --ca-cert roots, not the system store
+    #[clap(long, global = true)]
+    pub pin_roots: bool,
+
+    /// Client certificate, PEM with --client-key, otherwise PKCS#12
+    #[clap(long, global = true)]
+    pub client_cert: Option<String>,
+
+    /// Client private key ( PKCS#8 PEM )
+    #[clap(long, global = true)]
+    pub client_key: Option<String>,
+
+    /// Password for a PKCS#12 client certificate
+    #[clap(long, global = true)]
+    pub client_cert_password: Option<String>,
+
+    /// Minimum TLS version: 1.0, 1.1, 1.2 or 1.3
+    #[clap(long, global = true)]
+    pub min_tls: Option<String>,
+
+    /// Write results to a file instead of stdout
+    #[clap(short, long, global = true)]
+    pub output: Option<String>,
+
+    /// Hide the progress bar
+    #[clap(long, global = true)]
+    pub no_progress: bool,
+
+    /// TOML file of default options, see the README, options on the command line win
+    #[clap(long, global = true)]
+    pub config: Option<String>,
+
+    /// The --cookies jar, loaded once and shared by every crawl
+    #[clap(skip)]
+    pub cookie_jar: Option<Arc<CookieJar>>,
+}
+
+impl HttpOptions {
+    /// Header profiles to send, none without --profile
+    fn profiles(&self) -> Result<Vec<HeaderProfile>> {
+        let Some(profile) = &self.profile else {
+            return Ok(vec![]);
+        };
+        Ok(match profile.as_str() {
+            "chrome" => vec![HeaderProfile::chrome()],
+            "firefox" => vec![HeaderProfile::firefox()],
+            "safari" => vec![HeaderProfile::safari()],
+            "rotate" => vec![
+                HeaderProfile::chrome(),
+                HeaderProfile::firefox(),
+                HeaderProfile::safari(),
+            ],
+            "bot" => vec![HeaderProfile::bot(
+                concat!("gar-crawl-cli/", env!("CARGO_PKG_VERSION")),
+                "https://github.com/garlic0x1/gar-crawl",
+            )],
+            other => anyhow::bail!("unknown profile: {other}"),
+        })
+    }
+
+    /// -H headers and --user-agent, in order, with the default User-Agent if none is set
+    fn headers(&self) -> Result<Vec<(&str, &str)>> {
+        let mut headers = vec![];
+        for header in self.header.iter() {
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("header should be \"Name: value\": {header}"))?;
+            headers.push((name.trim(), value.trim()));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            headers.push(("User-Agent", user_agent));
+        }
+        let has_user_agent = headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("user-agent"));
+        if self.profile.is_none() && !has_user_agent {
+            headers.push(("User-Agent", DEFAULT_USER_AGENT));
+        }
+        Ok(headers)
+    }
+
+    /// Apply timeout and TLS options to a client
+    fn configure(&self, mut cb: ClientBuilder) -> Result<ClientBuilder> {
+        cb = cb
+            .timeout(Duration::from_secs(self.timeout))
+            .danger_accept_invalid_certs(self.insecure);
+        for path in self.ca_cert.iter() {
+            cb = cb.add_root_certificate(load_certificate(path)?);
+        }
+        if self.pin_roots {
+            anyhow::ensure!(!self.ca_cert.is_empty(), "--pin-roots requires --ca-cert");
+            cb = cb.tls_built_in_root_certs(false);
+        }
+        cb = match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => cb.identity(Identity::from_pkcs8_pem(
+                &std::fs::read(cert)?,
+                &std::fs::read(key)?,
+            )?),
+            (Some(cert), None) => {
+                let password = self.client_cert_password.as_deref().unwrap_or_default();
+                cb.identity(Identity::from_pkcs12_der(&std::fs::read(cert)?, password)?)
+            }
+            (None, Some(_)) => anyhow::bail!("--client-key requires --client-cert"),
+            (None, None) => cb,
+        };
+        if let Some(version) = &self.min_tls {
+            cb = cb.min_tls_version(match version.as_str() {
+                "1.0" => Version::TLS_1_0,
+                "1.1" => Version::TLS_1_1,
+                "1.2" => Version::TLS_1_2,
+                "1.3" => Version::TLS_1_3,
+                other => anyhow::bail!("unknown TLS version: {other}"),
+            });
+        }
+        Ok(cb)
+    }
+
+    /// A crawler builder with every HTTP option applied, logging to stderr
+    pub fn crawler<'a>(
+        &'a self,
+        url: &str,
+        progress: &'a MultiProgress,
+    ) -> Result<CrawlerBuilder<'a>> {
+        let http = self.clone();
+        let mut builder = Crawler::builder()
+            .workers(self.workers)
+            .on_event(|event| log(event, self, progress))
+            .report_depth_skips(self.verbose >= 2)
+            .client_setting(move |cb| http.configure(cb));
+
+        for profile in self.profiles()? {
+            builder = builder.header_profile(profile);
+        }
+        builder = builder.profile_rotation(match self.profile_rotation.as_str() {
+            "host" => Rotation::PerHost,
+            "page" => Rotation::PerRequest,
+            other => anyhow::bail!("unknown profile rotation: {other}"),
+        });
+        for (name, value) in self.headers()? {
+            builder = builder.header(name, value)?;
+        }
+        for proxy in self.proxy.iter() {
+            builder = builder.add_proxy(ProxyConfig::new(proxy));
+        }
+        if let Some(jar) = &self.cookie_jar {
+            builder = builder.cookie_jar(jar.clone());
+        }
+        for cookie in self.cookie.iter() {
+            builder = builder.cookie_header(url, cookie)?;
+        }
+        Ok(builder)
+    }
+
+    /// A client with every HTTP option applied, the first profile and the first proxy
+    pub fn client(&self) -> Result<ClientBuilder> {
+        let mut headers = HeaderMap::new();
+        let profile = self.profiles()?.into_iter().next();
+        let profile_headers = profile.iter().flat_map(|profile| profile.headers.iter());
+        for (name, value) in profile_headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        for (name, value) in self.headers()? {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        if !self.cookie.is_empty() {
+            headers.insert(COOKIE, HeaderValue::from_str(&self.cookie.join("; "))?);
+        }
+
+        let mut cb = self.configure(Client::builder().default_headers(headers))?;
+        if let Some(proxy) = self.proxy.first() {
+            cb = ProxyConfig::new(proxy).apply(cb)?;
+        }
+        if let Some(jar) = &self.cookie_jar {
+            cb = cb.cookie_provider(jar.clone());
+        }
+        Ok(cb)
+    }
+
+    /// Load the --cookies file
+    fn load_cookies(&mut self) -> Result<()> {
+        if let Some(path) = &self.cookies {
+            self.cookie_jar = Some(Arc::new(CookieJar::load(path)?));
+        }
+        Ok(())
+    }
+
+    /// Save the --cookies file, once after every crawl has finished
+    fn save_cookies(&self) -> Result<()> {
+        if let (Some(jar), Some(path)) = (&self.cookie_jar, &self.cookies) {
+            jar.save(path)?;
+        }
+        Ok(())
+    }
+
+    /// Progress bars, hidden with --quiet or --no-progress
+    fn progress(&self) -> MultiProgress {
+        if self.no_progress || self.quiet {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        }
+    }
+
+    /// Stdout, or the --output file
+    fn output(&self) -> Result<Output> {
+        Ok(Arc::new(Mutex::new(match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(std::io::stdout()),
+        })))
+    }
+}
+
+/// Options for subcommands that crawl
+#[derive(Args)]
+pub struct ScopeArgs {
+    /// Start url ( will read lines from stdin if not provided as a flag )
     #[clap(short, long)]
-    revisit: bool,
+    pub url: Option<String>,
+
+    /// Crawl depth
+    #[clap(default_value_t = 2, short, long)]
+    pub depth: usize,
 
-    /// Verbose output
+    /// Revisit urls
     #[clap(short, long)]
-    verbose: bool,
+    pub revisit: bool,
 
     /// Confine crawl inside given path ( alias of whitelist(url) )
     #[clap(short, long)]
-    confine: bool,
+    pub confine: bool,
+
+    /// Only crawl urls containing this pattern ( repeatable )
+    #[clap(long)]
+    pub include: Vec<String>,
+
+    /// Don't crawl urls containing this pattern ( repeatable )
+    #[clap(long)]
+    pub exclude: Vec<String>,
+
+    /// Stop after requesting this many pages
+    #[clap(long)]
+    pub max_pages: Option<usize>,
+
+    /// Don't follow rel="nofollow" links or links on pages marked nofollow by robots meta tags
+    /// and X-Robots-Tag headers
+    #[clap(long)]
+    pub respect_robots: bool,
+
+    /// Crawl this many stdin urls at once
+    #[clap(default_value_t = 4, long)]
+    pub parallel: usize,
+}
+
+impl ScopeArgs {
+    /// Apply depth and scope options for a crawl starting at `url`
+    pub fn apply<'a>(&self, mut builder: CrawlerBuilder<'a>, url: &str) -> CrawlerBuilder<'a> {
+        builder = builder
+            .depth(self.depth)
+            .revisit(self.revisit)
+            .respect_robots(self.respect_robots);
+        if self.confine {
+            builder = builder.whitelist(url);
+        }
+        for pattern in self.include.iter() {
+            builder = builder.whitelist(pattern);
+        }
+        for pattern in self.exclude.iter() {
+            builder = builder.blacklist(pattern);
+        }
+        if let Some(max) = self.max_pages {
+            builder = builder.max_pages(max);
+        }
+        builder
+    }
+
+    /// The --url, or lines from stdin
+    pub fn urls(&self) -> Vec<String> {
+        match &self.url {
+            Some(url) => vec![url.clone()],
+            None => std::io::stdin().lines().map_while(Result::ok).collect(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Arc::new(Arguments::parse());
-
-    let futures = match &args.url {
-        Some(url) => vec![crawl(args.clone(), url.clone())],
-        None => std::io::stdin()
-            .lines()
-            .flatten()
-            .map(|line| crawl(args.clone(), line))
-            .collect(),
-    };
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    if let Some(path) = cli.http.config.clone() {
+        config::Config::read(&path)?.apply(&mut cli, &matches);
+    }
+    let mut http = cli.http;
+    http.load_cookies()?;
+    let progress = http.progress();
+    let output = http.output()?;
 
-    join_all(futures).await;
+    // exit with 1 if something failed or broken links were found
+    let ok = match &cli.command {
+        Command::Crawl(args) => crawl::run(&http, args, &progress, &output).await?,
+        Command::Fuzz(args) => fuzz::run(&http, args, &progress, &output).await?,
+        Command::Links(args) => links::run(&http, args, &progress, &output).await?,
+        Command::Sitemap(args) => sitemap::run(&http, args, &progress, &output).await?,
+    };
+    http.save_cookies()?;
+    output.lock().unwrap().flush()?;
+    if !ok {
+        std::process::exit(1);
+    }
 
     Ok(())
 }
 
-async fn crawl(args: Arc<Arguments>, url: String) -> Result<()> {
-    let mut seen: HashSet<String> = HashSet::new();
-
-    let mut builder = Crawler::builder()
-        .add_default_propagators()
-        // .proxy("127.0.0.1:8080", "examples/cacert.der")?
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/104.0.5112.79 Safari/537.36".into())
-        .workers(args.workers)
-        .revisit(args.revisit)
-        .on_page(|_args| {
-            //println!("{}", &args.page.url.as_str());
-        })
-        .add_handler("*[href]", |args| {
-            if let Some(href) = args.element.unwrap().value().attr("href") {
-                if let Ok(abs_url) = absolute_url(&args.page.url, href) {
-        if seen.insert(abs_url.to_string()) {
-            println!("{}", abs_url.as_str());
+/// Run `crawl` on every url, `parallel` at a time, printing errors with their url
+/// Results are in the order of `urls`
+pub async fn each_url<'a, F, Fut, T>(
+    urls: Vec<String>,
+    parallel: usize,
+    progress: &MultiProgress,
+    crawl: F,
+) -> Vec<Result<T>>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T>> + 'a,
+{
+    let futures = urls.into_iter().map(|url| {
+        let result = crawl(url.clone());
+        async move {
+            let result = result.await;
+            if let Err(err) = &result {
+                progress.suspend(|| eprintln!("{url}: {err:#}"));
+            }
+            result
         }
-                }
+    });
+    stream::iter(futures)
+        .buffered(parallel.max(1))
+        .collect()
+        .await
+}
+
+/// Crawl `url` with a progress bar, then print a summary unless quiet
+pub async fn crawl_with_progress(
+    crawler: &mut Crawler<'_>,
+    url: &str,
+    http: &HttpOptions,
+    progress: &MultiProgress,
+) -> Result<()> {
+    let bar = progress.add(ProgressBar::new(0));
+    bar.set_style(ProgressStyle::with_template(
+        "{spinner} {prefix} [{bar:30}] {pos}/{len} {msg}",
+    )?);
+    bar.set_prefix(url.to_string());
+    let stats = crawler.stats();
+    let ticker = {
+        let bar = bar.clone();
+        tokio::spawn(async move {
+            loop {
+                let s = stats.snapshot();
+                bar.set_length((s.pages + s.failed + s.queued + s.in_flight) as u64);
+                bar.set_position((s.pages + s.failed) as u64);
+                bar.set_message(format!(
+                    "{:.1} req/s, {} failed",
+                    s.requests_per_sec(),
+                    s.failed
+                ));
+                tokio::time::sleep(Duration::from_millis(100)).await;
             }
         })
-        .depth(args.depth)
-        .timeout(args.timeout, 0);
+    };
+
+    let result = crawler.crawl(url).await;
+    ticker.abort();
+    bar.finish_and_clear();
+    result?;
 
-    if args.confine {
-        builder = builder.whitelist(&url);
+    if !http.quiet {
+        let s = crawler.stats().snapshot();
+        let mut failed = s.failed.to_string();
+        if !s.errors.is_empty() {
+            let kinds: Vec<String> = s
+                .errors
+                .iter()
+                .map(|(kind, n)| format!("{n} {kind}"))
+                .collect();
+            failed = format!("{failed} ({})", kinds.join(", "));
+        }
+        let statuses: Vec<String> = s
+            .statuses
+            .iter()
+            .map(|(code, n)| format!("{code}: {n}"))
+            .collect();
+        progress.suspend(|| {
+            eprintln!(
+                "{url}: {} pages, {failed} failed in {:.1}s, {:.1} req/s, {} KiB [{}]",
+                s.pages,
+                s.elapsed.as_secs_f64(),
+                s.requests_per_sec(),
+                s.bytes / 1024,
+                statuses.join(", "),
+            )
+        });
     }
+    Ok(())
+}
 
-    builder.build()?.crawl(&url).await?;
+/// Write a line of results, hiding the progress bar while writing to stdout
+pub fn write_line(output: &Output, progress: &MultiProgress, line: &str) {
+    progress.suspend(|| {
+        let _ = writeln!(output.lock().unwrap(), "{line}");
+    });
+}
 
-    Ok(())
+/// Log lifecycle events on stderr according to the verbosity
+fn log(event: &CrawlEvent, http: &HttpOptions, progress: &MultiProgress) {
+    let line = match event {
+        CrawlEvent::FetchError { url, error, .. } if !http.quiet => {
+            format!("error {url}: {error}")
+        }
+        CrawlEvent::Response {
+            url,
+            depth,
+            status,
+            bytes,
+            elapsed,
+            ..
+        } if http.verbose >= 1 => format!(
+            "{} depth {depth} {} {}ms {url}",
+            status.as_u16(),
+            HumanBytes(*bytes as u64),
+            elapsed.as_millis()
+        ),
+        CrawlEvent::Request { url, method } if http.verbose >= 2 => format!("{method} {url}"),
+        CrawlEvent::Skip { url, reason, .. } if http.verbose >= 2 => {
+            format!("skip {url} ({reason:?})")
+        }
+        _ => return,
+    };
+    progress.suspend(|| eprintln!("{line}"));
 }
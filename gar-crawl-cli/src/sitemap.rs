@@ -0,0 +1,116 @@
+use crate::{crawl_with_progress, each_url, write_line, HttpOptions, Output, ScopeArgs};
+use anyhow::{bail, Result};
+use clap::Args;
+use gar_crawl::crawler::Sitemap;
+use indicatif::MultiProgress;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Args)]
+pub struct SitemapArgs {
+    #[clap(flatten)]
+    pub scope: ScopeArgs,
+
+    /// Write sitemap.xml, and sitemap-N.xml files if it has to be split, into this directory  
+    /// Start urls on several hosts get a directory each, named after the host
+    #[clap(long)]
+    pub dir: Option<String>,
+
+    /// Url the sitemap files will be served from, for the index ( default: root of the host )  
+    /// Only for start urls on one host
+    #[clap(long)]
+    pub base: Option<String>,
+}
+
+/// Crawl every start url and write a sitemap per host of the pages that can be indexed
+pub async fn run(
+    http: &HttpOptions,
+    args: &SitemapArgs,
+    progress: &MultiProgress,
+    output: &Output,
+) -> Result<bool> {
+    let urls = args.scope.urls();
+    // the root of each start url's host, pages are only listed under their own host
+    let mut roots: BTreeMap<String, Url> = BTreeMap::new();
+    for url in urls.iter() {
+        let url = Url::parse(url)?;
+        let host = url.host_str().unwrap_or_default().to_string();
+        roots.entry(host).or_insert(url.join("/")?);
+    }
+    match (&args.base, roots.len()) {
+        (_, 0) => bail!("no start urls"),
+        (Some(_), 1) | (None, _) => (),
+        (Some(_), hosts) => {
+            bail!("--base only works for one host, the start urls are on {hosts} hosts")
+        }
+    }
+    if roots.len() > 1 && args.dir.is_none() {
+        bail!("the start urls are on {} hosts, use --dir", roots.len());
+    }
+
+    let sitemaps: Mutex<BTreeMap<String, Sitemap>> = Mutex::default();
+    let results = each_url(urls, args.scope.parallel, progress, |url| {
+        crawl(http, &args.scope, url, progress, &sitemaps)
+    })
+    .await;
+    let sitemaps = sitemaps.into_inner().unwrap();
+
+    for (host, root) in roots.iter() {
+        let sitemap = sitemaps.get(host).cloned().unwrap_or_default();
+        let base = match &args.base {
+            Some(base) => Url::parse(base)?,
+            None => root.clone(),
+        };
+        match &args.dir {
+            Some(dir) => {
+                let dir = match roots.len() {
+                    1 => PathBuf::from(dir),
+                    _ => Path::new(dir).join(host),
+                };
+                std::fs::create_dir_all(&dir)?;
+                for path in sitemap.write(&dir, &base)? {
+                    write_line(output, progress, &path.display().to_string());
+                }
+            }
+            None => {
+                let mut files = sitemap.files(&base)?;
+                if files.len() > 1 {
+                    bail!("the sitemap needs {} files, use --dir", files.len());
+                }
+                let (_, xml) = files.remove(0);
+                write_line(output, progress, xml.trim_end());
+            }
+        }
+    }
+
+    Ok(results.iter().all(Result::is_ok))
+}
+
+/// Crawl `url`, adding its pages to the sitemap for its host
+async fn crawl(
+    http: &HttpOptions,
+    scope: &ScopeArgs,
+    url: String,
+    progress: &MultiProgress,
+    sitemaps: &Mutex<BTreeMap<String, Sitemap>>,
+) -> Result<()> {
+    let builder = http
+        .crawler(&url, progress)?
+        .add_default_propagators()
+        .sitemap(true);
+    let mut crawler = scope.apply(builder, &url).build()?;
+    let result = crawl_with_progress(&mut crawler, &url, http, progress).await;
+    // the crawler only lists pages on the start url's host
+    if let Some(found) = crawler.sitemap() {
+        let host = found.host.clone().unwrap_or_default();
+        let mut sitemaps = sitemaps.lock().unwrap();
+        sitemaps
+            .entry(host)
+            .or_default()
+            .urls
+            .extend(found.urls.clone());
+    }
+    result
+}
@@ -1,6 +1,7 @@
 use anyhow::{bail, Result};
-use reqwest::Url;
+use reqwest::{Certificate, Url};
 use std::collections::HashSet;
+use std::fs::read;
 
 /// absolute url relative to base
 pub fn absolute_url(base_url: &Url, href: &str) -> Result<Url> {
@@ -13,8 +14,25 @@ pub fn absolute_url(base_url: &Url, href: &str) -> Result<Url> {
     }
 }
 
+/// quote a CSV field if it needs it
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// escape text for XML content and attribute values
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 /// match whitelist/blacklist rules
-pub fn is_allowed(url: &Url, wl: &Vec<String>, bl: &Vec<String>) -> bool {
+pub fn is_allowed(url: &Url, wl: &[String], bl: &[String]) -> bool {
     let surl = url.to_string();
     if wl
         .iter()
@@ -43,3 +61,13 @@ pub fn is_visited(url: &Url, visited: &mut HashSet<String>) -> bool {
     let surl = url.to_string();
     !visited.insert(surl)
 }
+
+/// load a PEM or DER certificate file
+pub fn load_certificate(path: &str) -> Result<Certificate> {
+    let buf = read(path)?;
+    if buf.starts_with(b"-----BEGIN") {
+        Ok(Certificate::from_pem(&buf)?)
+    } else {
+        Ok(Certificate::from_der(&buf)?)
+    }
+}
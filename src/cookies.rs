@@ -0,0 +1,336 @@
+use anyhow::{anyhow, bail, Result};
+use reqwest::cookie::CookieStore;
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use std::fs::{read_to_string, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A stored cookie, with the fields of a Netscape cookies.txt line
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cookie {
+    /// Domain without a leading dot
+    pub domain: String,
+    /// Also send to subdomains of `domain`
+    pub include_subdomains: bool,
+    /// Path prefix
+    pub path: String,
+    /// Only send over https
+    pub secure: bool,
+    /// Expiry as a unix timestamp, 0 for session cookies
+    pub expires: u64,
+    /// Cookie name
+    pub name: String,
+    /// Cookie value
+    pub value: String,
+    /// Hidden from scripts, saved with a `#HttpOnly_` prefix
+    pub http_only: bool,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires != 0 && self.expires <= now
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default();
+        let domain_ok = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+        // "/account" matches "/account" and "/account/x", not "/accounts"
+        let path_ok = url.path() == self.path
+            || (url.path().starts_with(&self.path)
+                && (self.path.ends_with('/') || url.path()[self.path.len()..].starts_with('/')));
+        let secure_ok = !self.secure || url.scheme() == "https";
+        domain_ok && path_ok && secure_ok
+    }
+
+    /// Parse a `Set-Cookie` header value received from `url`  
+    /// Fails if `Domain` is not the host of `url` or a parent of it, or is a public suffix
+    pub fn parse_set_cookie(header: &str, url: &Url) -> Result<Self> {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let mut parts = header.split(';');
+        let (name, value) = parts
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .ok_or_else(|| anyhow!("Invalid Set-Cookie: {header}"))?;
+
+        let mut cookie = Cookie {
+            domain: host.clone(),
+            include_subdomains: false,
+            path: default_path(url),
+            secure: false,
+            expires: 0,
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            http_only: false,
+        };
+
+        let mut max_age = None;
+        for attr in parts {
+            let (key, val) = match attr.split_once('=') {
+                Some((key, val)) => (key.trim(), val.trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !val.is_empty() => {
+                    let domain = val.trim_start_matches('.').to_ascii_lowercase();
+                    if is_public_suffix(&domain) && domain == host {
+                        // a public suffix host can only set host-only cookies
+                        continue;
+                    }
+                    if is_public_suffix(&domain)
+                        || !(host == domain || host.ends_with(&format!(".{domain}")))
+                    {
+                        bail!("Cookie domain {domain} not allowed for {host}");
+                    }
+                    cookie.domain = domain;
+                    cookie.include_subdomains = true;
+                }
+                "path" if val.starts_with('/') => cookie.path = val.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => max_age = val.parse::<i64>().ok(),
+                "expires" => {
+                    if let Ok(time) = httpdate::parse_http_date(val) {
+                        cookie.expires = unix_time(time).max(1);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Max-Age takes precedence over Expires
+        if let Some(secs) = max_age {
+            cookie.expires = match secs {
+                secs if secs <= 0 => 1,
+                secs => unix_time(SystemTime::now() + Duration::from_secs(secs as u64)),
+            };
+        }
+
+        Ok(cookie)
+    }
+
+    /// Parse a line of a Netscape cookies.txt file
+    pub fn parse_netscape(line: &str) -> Result<Self> {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            bail!("Invalid cookies.txt line: {line}");
+        }
+        Ok(Cookie {
+            domain: fields[0].trim_start_matches('.').to_string(),
+            include_subdomains: fields[1].eq_ignore_ascii_case("true"),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("true"),
+            expires: fields[4].parse()?,
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+            http_only,
+        })
+    }
+
+    /// Format as a line of a Netscape cookies.txt file
+    pub fn to_netscape(&self) -> String {
+        let bool_str = |b: bool| if b { "TRUE" } else { "FALSE" };
+        let domain = if self.include_subdomains {
+            format!(".{}", self.domain)
+        } else {
+            self.domain.clone()
+        };
+        format!(
+            "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { "#HttpOnly_" } else { "" },
+            domain,
+            bool_str(self.include_subdomains),
+            self.path,
+            bool_str(self.secure),
+            self.expires,
+            self.name,
+            self.value
+        )
+    }
+}
+
+/// A cookie store for the reqwest client that can be loaded from and saved to cookies.txt
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: RwLock<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the contents of a Netscape cookies.txt file
+    pub fn from_netscape(text: &str) -> Result<Self> {
+        let jar = Self::new();
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || (line.starts_with('#') && !line.starts_with("#HttpOnly_"))
+            {
+                continue;
+            }
+            jar.insert(Cookie::parse_netscape(line)?);
+        }
+        Ok(jar)
+    }
+
+    /// Load a Netscape cookies.txt file, empty if it doesn't exist
+    pub fn load(path: &str) -> Result<Self> {
+        if Path::new(path).exists() {
+            Self::from_netscape(&read_to_string(path)?)
+        } else {
+            Ok(Self::new())
+        }
+    }
+
+    /// Save unexpired cookies as a Netscape cookies.txt file
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.to_netscape().as_bytes())?;
+        Ok(())
+    }
+
+    /// Format unexpired cookies as a Netscape cookies.txt file
+    pub fn to_netscape(&self) -> String {
+        let mut text = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in self.all() {
+            text.push_str(&cookie.to_netscape());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Add cookies from a raw `Cookie` header, sent to the host of `url`
+    pub fn add_cookie_header(&self, header: &str, url: &Url) {
+        header
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .for_each(|(name, value)| {
+                self.insert(Cookie {
+                    domain: url.host_str().unwrap_or_default().to_string(),
+                    include_subdomains: false,
+                    path: "/".to_string(),
+                    secure: false,
+                    expires: 0,
+                    name: name.trim().to_string(),
+                    value: value.trim().to_string(),
+                    http_only: false,
+                })
+            });
+    }
+
+    /// Add or replace a cookie, expired cookies remove their match
+    pub fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.write().unwrap();
+        cookies.retain(|c| {
+            !(c.domain == cookie.domain && c.path == cookie.path && c.name == cookie.name)
+        });
+        if !cookie.is_expired(unix_time(SystemTime::now())) {
+            cookies.push(cookie);
+        }
+    }
+
+    /// Unexpired cookies in the jar
+    pub fn all(&self) -> Vec<Cookie> {
+        let now = unix_time(SystemTime::now());
+        self.cookies
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| !c.is_expired(now))
+            .cloned()
+            .collect()
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        cookie_headers
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| Cookie::parse_set_cookie(value, url).ok())
+            .for_each(|cookie| self.insert(cookie));
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self
+            .all()
+            .iter()
+            .filter(|c| c.matches(url))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<String>>()
+            .join("; ");
+        if header.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&header).ok()
+        }
+    }
+}
+
+/// Whether cookies may not be scoped to `domain`: single labels like `com` and common
+/// multi-label suffixes like `co.uk` or `github.io`  
+/// This is a short built-in list, not the full Public Suffix List
+fn is_public_suffix(domain: &str) -> bool {
+    const SUFFIXES: &[&str] = &[
+        "co.uk",
+        "org.uk",
+        "ac.uk",
+        "gov.uk",
+        "me.uk",
+        "ltd.uk",
+        "plc.uk",
+        "com.au",
+        "net.au",
+        "org.au",
+        "edu.au",
+        "gov.au",
+        "co.nz",
+        "org.nz",
+        "co.jp",
+        "ne.jp",
+        "or.jp",
+        "com.br",
+        "com.cn",
+        "net.cn",
+        "org.cn",
+        "com.mx",
+        "co.in",
+        "co.za",
+        "com.tr",
+        "com.tw",
+        "co.kr",
+        "github.io",
+        "gitlab.io",
+        "herokuapp.com",
+        "appspot.com",
+        "blogspot.com",
+        "azurewebsites.net",
+        "cloudfront.net",
+        "netlify.app",
+        "vercel.app",
+        "pages.dev",
+        "workers.dev",
+        "s3.amazonaws.com",
+    ];
+    !domain.contains('.') || SUFFIXES.contains(&domain)
+}
+
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => url.path()[..i].to_string(),
+    }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
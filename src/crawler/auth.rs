@@ -0,0 +1,176 @@
+use super::courier::{send, Courier, Job};
+use super::form::Form;
+use anyhow::{anyhow, bail, Result};
+use reqwest::header::{HeaderName, HeaderValue, WWW_AUTHENTICATE};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use scraper::Html;
+use std::collections::HashMap;
+
+/// Url fragments that usually end a session, see `CrawlerBuilder::logout_guard`
+pub const LOGOUT_PATTERNS: [&str; 6] = [
+    "logout", "log-out", "logoff", "log-off", "signout", "sign-out",
+];
+
+/// Credentials sent with requests to the start url's origin, see `CrawlerBuilder::auth_host`
+#[derive(Clone, Debug)]
+pub enum Auth {
+    /// Static headers
+    Headers(Vec<(String, String)>),
+    /// HTTP basic auth
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// HTTP digest auth, answered when the server sends a challenge
+    Digest { username: String, password: String },
+    /// `Authorization: Bearer` token
+    Bearer(String),
+}
+
+impl Auth {
+    /// Add credentials to a request
+    pub fn apply(&self, builder: RequestBuilder) -> Result<RequestBuilder> {
+        Ok(match self {
+            Auth::Headers(headers) => {
+                let mut builder = builder;
+                for (name, value) in headers {
+                    builder = builder.header(
+                        HeaderName::from_bytes(name.as_bytes())?,
+                        HeaderValue::from_str(value)?,
+                    );
+                }
+                builder
+            }
+            Auth::Basic { username, password } => builder.basic_auth(username, password.as_ref()),
+            Auth::Digest { .. } => builder,
+            Auth::Bearer(token) => builder.bearer_auth(token),
+        })
+    }
+
+    /// `Authorization` header answering a digest challenge in `response`, if there is one
+    pub fn digest_response(&self, method: &Method, response: &Response) -> Option<String> {
+        let (username, password) = match self {
+            Auth::Digest { username, password } => (username, password),
+            _ => return None,
+        };
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return None;
+        }
+
+        let challenge = response.headers().get(WWW_AUTHENTICATE)?.to_str().ok()?;
+        let mut prompt = digest_auth::parse(challenge).ok()?;
+        let uri = match response.url().query() {
+            Some(query) => format!("{}?{}", response.url().path(), query),
+            None => response.url().path().to_string(),
+        };
+        let context = digest_auth::AuthContext::new_with_method(
+            username.as_str(),
+            password.as_str(),
+            uri,
+            None::<&[u8]>,
+            digest_auth::HttpMethod(method.as_str().to_string().into()),
+        );
+        prompt
+            .respond(&context)
+            .ok()
+            .map(|header| header.to_string())
+    }
+}
+
+/// A login form submitted before crawling and again when the session is lost
+#[derive(Clone, Debug)]
+pub struct FormLogin {
+    /// Url the login form is POSTed to
+    pub url: Url,
+    /// Credentials and other fields to submit
+    pub fields: HashMap<String, String>,
+    /// Page with the login form, fetched first to pick up hidden fields like CSRF tokens
+    pub form_page: Option<Url>,
+    /// Url fragments that mean the session was lost, such as a redirect to the login page
+    /// If empty, landing on `url` or `form_page` counts as logged out
+    pub logged_out: Vec<String>,
+}
+
+impl FormLogin {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(url)?,
+            fields: HashMap::new(),
+            form_page: None,
+            logged_out: vec![],
+        })
+    }
+
+    /// Submit `name` with `value`
+    pub fn field(mut self, name: &str, value: &str) -> Self {
+        self.fields.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Fetch the form from this page first and submit its other fields too
+    pub fn form_page(mut self, url: &str) -> Result<Self> {
+        self.form_page = Some(Url::parse(url)?);
+        Ok(self)
+    }
+
+    /// Treat landing on a url containing `expr` as logged out
+    pub fn logged_out_when(mut self, expr: &str) -> Self {
+        self.logged_out.push(expr.to_string());
+        self
+    }
+
+    /// Whether a response that ended up at `url` means the session was lost
+    pub fn is_logged_out(&self, url: &Url) -> bool {
+        if self.logged_out.is_empty() {
+            std::iter::once(&self.url)
+                .chain(self.form_page.iter())
+                .any(|login| login.host_str() == url.host_str() && login.path() == url.path())
+        } else {
+            let surl = url.to_string();
+            self.logged_out.iter().any(|expr| surl.contains(expr))
+        }
+    }
+
+    /// Submit the login form with the crawler's headers, hooks and cookie store
+    pub(crate) async fn login(&self, courier: &Courier) -> Result<()> {
+        let fields = match &self.form_page {
+            Some(page) => {
+                let fetched = send(Job::get(page.clone(), 0), courier).await?;
+                let forms = Form::extract(&Html::parse_document(&fetched.text), page);
+                let form = forms
+                    .iter()
+                    .find(|form| form.action == self.url)
+                    .or_else(|| {
+                        forms
+                            .iter()
+                            .find(|form| form.inputs.iter().any(|input| input.kind == "password"))
+                    })
+                    .ok_or_else(|| anyhow!("No login form found on {page}"))?;
+                form.fill(&self.fields)
+            }
+            None => self
+                .fields
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        };
+
+        let job = Job {
+            form: Some(fields),
+            submit: true,
+            ..Job::get(self.url.clone(), 0)
+        };
+        let res = send(job, courier).await?;
+        if res.status.is_client_error() || res.status.is_server_error() {
+            bail!("Login failed with status {}", res.status);
+        }
+        if self
+            .logged_out
+            .iter()
+            .any(|expr| res.final_url.as_str().contains(expr))
+        {
+            bail!("Login failed, landed on {}", res.final_url);
+        }
+        Ok(())
+    }
+}
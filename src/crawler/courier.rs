@@ -1,32 +1,360 @@
+use super::auth::Auth;
+use super::handler::RequestHook;
+use super::redirect::{Redirect, RedirectPolicy};
+use crate::profile::{HeaderProfile, ProfilePool};
+use crate::proxy::ProxyPool;
 use anyhow::{anyhow, Result};
 use async_channel::*;
-use reqwest::{Client, Url};
-use std::sync::Arc;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, LOCATION, PROXY_AUTHORIZATION,
+};
+use reqwest::{Client, Method, Request, Response, StatusCode, Url};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// A queued request
+pub struct Job {
+    /// Url to request
+    pub url: Url,
+    /// Crawl depth of the resulting page
+    pub depth: usize,
+    /// Page the url was found on
+    pub referrer: Option<Url>,
+    /// Fields to POST as a urlencoded form, GET if None
+    pub form: Option<Vec<(String, String)>>,
+    /// Already requeued after logging in again
+    pub reauth: bool,
+    /// Only check the link with a HEAD request, the response is not handled
+    pub check: bool,
+    /// Submits a form instead of following a link
+    pub submit: bool,
+}
+
+impl Job {
+    pub fn get(url: Url, depth: usize) -> Self {
+        Self {
+            url,
+            depth,
+            referrer: None,
+            form: None,
+            reauth: false,
+            check: false,
+            submit: false,
+        }
+    }
+
+    pub fn head(url: Url, depth: usize) -> Self {
+        Self {
+            check: true,
+            ..Self::get(url, depth)
+        }
+    }
+
+    /// Method of the first request
+    pub fn method(&self) -> Method {
+        match (&self.form, self.check) {
+            (Some(_), _) => Method::POST,
+            (None, true) => Method::HEAD,
+            (None, false) => Method::GET,
+        }
+    }
+}
+
+/// A fetched page
+pub struct Fetched {
+    /// The job that produced this page
+    pub job: Job,
+    /// Url after redirects
+    pub final_url: Url,
+    /// Redirects encountered, in order
+    pub redirects: Vec<Redirect>,
+    /// Status of the last response
+    pub status: StatusCode,
+    /// Headers of the last response
+    pub headers: HeaderMap,
+    /// Response body
+    pub text: String,
+    /// Time taken to fetch, including redirects
+    pub elapsed: Duration,
+}
+
+/// A job that could not be fetched
+pub struct Failed {
+    /// Url of the job
+    pub url: Url,
+    /// The job submitted a form
+    pub submit: bool,
+    /// What went wrong
+    pub error: anyhow::Error,
+    /// Time taken to fail
+    pub elapsed: Duration,
+}
+
+/// Everything needed to make requests
+pub struct Courier {
+    pub pool: ProxyPool,
+    pub auth: Option<Auth>,
+    pub headers: HeaderMap,
+    pub profiles: ProfilePool,
+    pub hooks: Vec<RequestHook>,
+    pub redirect: RedirectPolicy,
+    pub whitelist: Vec<String>,
+    pub blacklist: Vec<String>,
+    /// Hosts credentials are sent to, the origin of `start` if empty
+    pub auth_hosts: Vec<String>,
+    /// Url the current crawl started at
+    pub start: RwLock<Option<Url>>,
+}
+
+impl Courier {
+    /// Whether credentials may be sent to `url`
+    fn authorizes(&self, url: &Url) -> bool {
+        if self.auth_hosts.is_empty() {
+            let start = self.start.read().unwrap();
+            start
+                .as_ref()
+                .is_some_and(|start| start.origin() == url.origin())
+        } else {
+            let host = url.host_str().unwrap_or_default();
+            self.auth_hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+        }
+    }
+
+    /// Build a request, `trusted` is false after a redirect to another origin
+    #[allow(clippy::too_many_arguments)]
+    fn request(
+        &self,
+        client: &Client,
+        url: &Url,
+        method: &Method,
+        form: Option<&Vec<(String, String)>>,
+        referrer: Option<&Url>,
+        profile: Option<&HeaderProfile>,
+        authorization: Option<HeaderValue>,
+        trusted: bool,
+    ) -> Result<Request> {
+        let mut headers = HeaderMap::new();
+        if let Some(profile) = profile {
+            for (name, value) in profile.headers_for(url, referrer) {
+                headers.insert(
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                );
+            }
+        }
+        // default headers override the profile
+        for name in self.headers.keys() {
+            headers.remove(name);
+        }
+        headers.extend(self.headers.clone());
+        if !trusted {
+            self.strip_credentials(&mut headers);
+        }
+
+        let mut builder = client.request(method.clone(), url.clone()).headers(headers);
+        if let Some(fields) = form {
+            builder = builder.form(fields);
+        }
+        if let Some(auth) = self
+            .auth
+            .as_ref()
+            .filter(|_| trusted && self.authorizes(url))
+        {
+            builder = auth.apply(builder)?;
+        }
+        if let Some(value) = authorization {
+            builder = builder.header(AUTHORIZATION, value);
+        }
+
+        let mut request = builder.build()?;
+        self.hooks.iter().for_each(|hook| hook(&mut request));
+        Ok(request)
+    }
+
+    /// Remove headers carrying credentials, including the static auth headers
+    fn strip_credentials(&self, headers: &mut HeaderMap) {
+        for name in [AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION] {
+            headers.remove(name);
+        }
+        if let Some(Auth::Headers(auth)) = &self.auth {
+            for (name, _) in auth {
+                headers.remove(name.as_str());
+            }
+        }
+    }
+}
 
 /// make a request and send the results on the async chan
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "fetch", skip_all, fields(url = %job.url, depth = job.depth))
+)]
 pub async fn fetch(
-    url: Url,
-    depth: usize,
-    client: Arc<Client>,
-    sender: Sender<Result<(Url, String, usize)>>,
+    job: Job,
+    courier: Arc<Courier>,
+    sender: Sender<Result<Fetched, Failed>>,
 ) -> Result<()> {
     // Must send a message or die trying
-    match client.get(url.clone()).send().await {
-        Ok(res) => match res.text().await {
-            Ok(text) => {
-                sender.send(Ok((url, text, depth))).await.unwrap();
-                Ok(())
-            }
-            Err(err) => {
-                let err = anyhow!(err);
-                sender.send(Err(err)).await.unwrap();
-                Err(anyhow!("Failed read"))
-            }
-        },
-        Err(err) => {
-            let err = anyhow!(err);
-            sender.send(Err(err)).await.unwrap();
+    let url = job.url.clone();
+    let submit = job.submit;
+    let started = Instant::now();
+    match send(job, &courier).await {
+        Ok(mut fetched) => {
+            fetched.elapsed = started.elapsed();
+            sender.send(Ok(fetched)).await.unwrap();
+            Ok(())
+        }
+        Err(error) => {
+            let elapsed = started.elapsed();
+            sender
+                .send(Err(Failed {
+                    url,
+                    submit,
+                    error,
+                    elapsed,
+                }))
+                .await
+                .unwrap();
             Err(anyhow!("Failed request"))
         }
     }
 }
+
+/// request a job, following redirects allowed by the policy
+pub async fn send(job: Job, courier: &Courier) -> Result<Fetched> {
+    let mut url = job.url.clone();
+    let mut form = job.form.clone();
+    let mut redirects: Vec<Redirect> = vec![];
+    let mut method = job.method();
+    let (index, client) = courier.pool.pick();
+    // redirects and retries are part of the same request, so keep one profile
+    let profile = courier.profiles.pick(&job.url);
+
+    loop {
+        let referrer = job.referrer.as_ref();
+        // credentials don't follow a redirect to another origin, such as a downgrade to http
+        let trusted = url.origin() == job.url.origin();
+        let request = courier.request(
+            &client,
+            &url,
+            &method,
+            form.as_ref(),
+            referrer,
+            profile,
+            None,
+            trusted,
+        )?;
+        let mut res = execute(courier, index, &client, request).await?;
+
+        // answer a digest challenge once
+        let digest = courier
+            .auth
+            .as_ref()
+            .filter(|_| trusted && courier.authorizes(&url))
+            .and_then(|auth| auth.digest_response(&method, &res));
+        if let Some(header) = digest {
+            let header = HeaderValue::from_str(&header)?;
+            let request = courier.request(
+                &client,
+                &url,
+                &method,
+                form.as_ref(),
+                referrer,
+                profile,
+                Some(header),
+                trusted,
+            )?;
+            res = execute(courier, index, &client, request).await?;
+        }
+
+        let status = res.status();
+
+        // some servers refuse HEAD, ask again with GET
+        let refused = [StatusCode::METHOD_NOT_ALLOWED, StatusCode::NOT_IMPLEMENTED];
+        if method == Method::HEAD && refused.contains(&status) {
+            method = Method::GET;
+            continue;
+        }
+
+        let location = res
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| url.join(value).ok());
+
+        let to = match location {
+            Some(to) if status.is_redirection() => to,
+            _ => return finish(job, url, redirects, res).await,
+        };
+
+        redirects.push(Redirect {
+            from: url.clone(),
+            to: to.clone(),
+            status,
+            followed: true,
+        });
+        if !courier
+            .redirect
+            .follows(&redirects, &courier.whitelist, &courier.blacklist)
+        {
+            redirects.last_mut().unwrap().followed = false;
+            return finish(job, url, redirects, res).await;
+        }
+
+        // only 307 and 308 keep the method and body
+        if status != StatusCode::TEMPORARY_REDIRECT && status != StatusCode::PERMANENT_REDIRECT {
+            form = None;
+            if method == Method::POST {
+                method = Method::GET;
+            }
+        }
+        url = to;
+    }
+}
+
+/// read the final response of a job
+async fn finish(
+    job: Job,
+    final_url: Url,
+    redirects: Vec<Redirect>,
+    res: Response,
+) -> Result<Fetched> {
+    Ok(Fetched {
+        job,
+        final_url,
+        redirects,
+        status: res.status(),
+        headers: res.headers().clone(),
+        text: res.text().await?,
+        elapsed: Duration::ZERO,
+    })
+}
+
+/// execute a request and report the outcome to the proxy pool
+async fn execute(
+    courier: &Courier,
+    index: usize,
+    client: &Client,
+    request: Request,
+) -> Result<Response> {
+    let res = client.execute(request).await;
+    // the target being down or slow is not the proxy's fault
+    let proxy_failed = match &res {
+        Ok(res) => res.status() == StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+        Err(err) => err.is_connect() && !is_dns_error(err),
+    };
+    courier.pool.report(index, !proxy_failed);
+    Ok(res?)
+}
+
+/// the target host could not be resolved
+fn is_dns_error(err: &reqwest::Error) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(err);
+    while let Some(err) = source {
+        if err.to_string().contains("dns error") {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
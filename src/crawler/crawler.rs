@@ -1,25 +1,46 @@
-// use super::courier;
-
+use super::auth::FormLogin;
+use super::courier::{self, Courier, Failed, Fetched, Job};
+use super::pipeline::{self, Closures, Handled, Parser};
 use crate::auxiliary::*;
+use crate::cookies::CookieJar;
+use crate::crawler::stats::error_kind;
 use crate::crawler::*;
-use anyhow::{bail, Result};
+use crate::profile::ProfilePool;
+use crate::proxy::{ProxyConfig, ProxyHealth, ProxyPool};
+use anyhow::Result;
 use async_channel::*;
-use reqwest::{Client, Url};
-use scraper::{Html, Selector};
+use reqwest::{Client, ClientBuilder, Method, Url};
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 /// A crawler object, use builder() to build with CrawlerBuilder
 pub struct Crawler<'a> {
-    handlers: HashMap<HandlerEvent, Vec<Handler<'a>>>,
-    propagators: HashMap<HandlerEvent, Vec<Propagator<'a>>>,
+    handlers: HashMap<HandlerEvent, Vec<Filtered<Handler<'a>>>>,
+    propagators: HashMap<HandlerEvent, Vec<Filtered<Propagator<'a>>>>,
     depth: usize,
+    max_pages: Option<usize>,
     workers: usize,
+    parse_workers: usize,
     client: Arc<Client>,
+    courier: Arc<Courier>,
     blacklist: Vec<String>,
     whitelist: Vec<String>,
     visited: HashSet<String>,
     revisit: bool,
+    submit_forms: bool,
+    respect_robots: bool,
+    report_depth_skips: bool,
+    form_values: HashMap<String, String>,
+    inventory: Option<Inventory>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    cookie_file: Option<String>,
+    login: Option<FormLogin>,
+    listeners: Vec<Listener<'a>>,
+    stats: CrawlStats,
+    summary: CrawlSummary,
+    link_graph: Option<LinkGraph>,
+    link_check: Option<LinkCheck>,
+    sitemap: Option<Sitemap>,
 }
 
 impl<'a> Crawler<'a> {
@@ -32,153 +53,481 @@ impl<'a> Crawler<'a> {
     /// Create a crawler, consuming a CrawlerBuilder
     /// Equivalent to `CrawlerBuilder.build()`
     pub fn from_builder(builder: CrawlerBuilder<'a>) -> Result<Self> {
+        let mut cookie_jar = builder.cookie_jar;
+
+        // form logins need somewhere to keep the session
+        if builder.login.is_some() && cookie_jar.is_none() {
+            cookie_jar = Some(Arc::new(CookieJar::new()));
+        }
+
+        let settings = &builder.client_settings;
+        let make_client = |mut cb: ClientBuilder, proxy: Option<&ProxyConfig>| -> Result<Client> {
+            for setting in settings.iter() {
+                cb = setting(cb)?;
+            }
+            if let Some(jar) = &cookie_jar {
+                cb = cb.cookie_provider(jar.clone());
+            }
+            if let Some(proxy) = proxy {
+                cb = proxy.apply(cb)?;
+            }
+            // the courier follows redirects itself to apply the redirect policy
+            cb = cb.redirect(reqwest::redirect::Policy::none());
+            Ok(cb.build()?)
+        };
+
+        // handlers share the first client, so they see every client change
+        let first_proxy = builder.proxies.first();
+        let client = Arc::new(make_client(builder.client_builder, first_proxy)?);
+        let mut clients = vec![(
+            first_proxy.map(|p| p.url.clone()).unwrap_or_default(),
+            client.clone(),
+        )];
+        for proxy in builder.proxies.iter().skip(1) {
+            let proxy_client = make_client(Client::builder(), Some(proxy))?;
+            clients.push((proxy.url.clone(), Arc::new(proxy_client)));
+        }
+
+        let courier = Arc::new(Courier {
+            pool: ProxyPool::new(clients, builder.proxy_max_failures)
+                .cooldown(builder.proxy_cooldown),
+            auth: builder.auth,
+            headers: builder.headers,
+            profiles: ProfilePool::new(builder.profiles, builder.profile_rotation),
+            hooks: builder.request_hooks,
+            redirect: builder.redirect_policy,
+            whitelist: builder.whitelist.clone(),
+            blacklist: builder.blacklist.clone(),
+            auth_hosts: builder.auth_hosts,
+            start: RwLock::new(None),
+        });
+
         Ok(Self {
             handlers: builder.handlers,
             propagators: builder.propagators,
             depth: builder.depth,
+            max_pages: builder.max_pages,
             workers: builder.workers,
-            client: Arc::new(builder.client_builder.build()?),
+            parse_workers: builder.parse_workers,
+            client,
+            courier,
             blacklist: builder.blacklist,
             whitelist: builder.whitelist,
             visited: HashSet::new(),
             revisit: builder.revisit,
+            submit_forms: builder.submit_forms,
+            respect_robots: builder.respect_robots,
+            report_depth_skips: builder.report_depth_skips,
+            form_values: builder.form_values,
+            inventory: builder.inventory.then(Inventory::default),
+            cookie_jar,
+            cookie_file: builder.cookie_file,
+            login: builder.login,
+            listeners: builder.listeners,
+            stats: CrawlStats::new(),
+            summary: CrawlSummary::default(),
+            link_graph: builder.link_graph.then(LinkGraph::default),
+            link_check: builder.link_check.then(LinkCheck::default),
+            sitemap: builder.sitemap.then(Sitemap::default),
         })
     }
 
     /// Start crawling at the provided URL and return errors that occur
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     pub async fn crawl(&mut self, start_url: &str) -> Result<Vec<anyhow::Error>> {
         let uri: Url = Url::parse(start_url)?;
         let mut errors = vec![];
         let mut seen: HashSet<Url> = HashSet::new();
         seen.insert(uri.clone());
+        self.stats.start();
+        *self.courier.start.write().unwrap() = Some(uri.clone());
+        if let Some(sitemap) = self.sitemap.as_mut() {
+            sitemap.host = uri.host_str().map(String::from);
+        }
+        emit(&mut self.listeners, CrawlEvent::Start { url: &uri });
 
         // set up async
-        let mut queue: VecDeque<(Url, usize)> = VecDeque::new();
-        queue.push_back((uri.clone(), 0));
+        let mut queue: VecDeque<Job> = VecDeque::new();
+        self.push_job(&mut queue, Job::get(uri.clone(), 0), None);
         let (s, r) = bounded(self.workers);
         let mut tasks = 0;
+        let mut requested = 0;
+
+        if let Some(login) = &self.login {
+            login.login(&self.courier).await?;
+        }
 
-        // Loop while the queue is not empty or tasks are fetching pages.
-        while queue.len() + tasks > 0 {
-            // Limit the number of concurrent tasks.
-            while tasks < s.capacity().unwrap() {
+        // propagators also run at the last depth when depth skips are reported
+        // or the link graph and link check want every link
+        let options = pipeline::Options {
+            propagate_below: if !self.report_depth_skips
+                && self.link_graph.is_none()
+                && self.link_check.is_none()
+            {
+                self.depth
+            } else {
+                usize::MAX
+            },
+            link_origins: self.link_graph.is_some(),
+        };
+        let mut parser = Parser::new(self.parse_workers);
+
+        // Loop while the queue is not empty or pages are being fetched or parsed.
+        while queue.len() + tasks + parser.queued() > 0 {
+            // Limit the number of pages fetched or waiting to be handled.
+            while tasks + parser.queued() < s.capacity().unwrap() {
                 // Process URLs in the queue and fetch more pages.
                 match queue.pop_front() {
                     None => break,
-                    Some(url) => {
+                    Some(job) => {
+                        self.stats.update(|stats| stats.queued = queue.len());
+                        // requeued and link check jobs don't count towards max pages
+                        if !job.check && !job.reauth {
+                            if self.max_pages.is_some_and(|max| requested >= max) {
+                                let referrer = job.referrer.as_ref().unwrap_or(&job.url);
+                                self.skip(&job.url, referrer, SkipReason::MaxPages);
+                                continue;
+                            }
+                            requested += 1;
+                        }
                         tasks += 1;
-                        tokio::spawn(courier::fetch(url.0, url.1, self.client.clone(), s.clone()));
+                        self.stats.update(|stats| stats.in_flight = tasks);
+                        let method = job.method();
+                        let event = CrawlEvent::Request {
+                            url: &job.url,
+                            method: &method,
+                        };
+                        emit(&mut self.listeners, event);
+                        tokio::spawn(courier::fetch(job, self.courier.clone(), s.clone()));
                     }
                 }
             }
 
-            // Recieve a message
-            let fetched = r.recv().await.unwrap();
-            tasks -= 1;
+            // Jobs past max pages may have emptied the queue with nothing left to wait for
+            if tasks + parser.queued() == 0 {
+                continue;
+            }
+
+            // Fetch while pages parse, waiting for whichever finishes first
+            tokio::select! {
+                fetched = r.recv(), if tasks > 0 => {
+                    // Recieve a message, and any others already waiting
+                    let mut batch = vec![fetched.unwrap()];
+                    while let Ok(fetched) = r.try_recv() {
+                        batch.push(fetched);
+                    }
+                    tasks -= batch.len();
+                    self.stats.update(|stats| stats.in_flight = tasks);
+                    for fetched in batch {
+                        if let Some(page) = self.receive(fetched, &mut queue, &mut errors).await {
+                            parser.submit(page);
+                        }
+                    }
+                }
+                _ = parser.recv(), if parser.parsing() => {}
+            }
 
-            if let Err(fetch_err) = fetched {
-                errors.push(fetch_err);
+            if !parser.has_ready() {
                 continue;
             }
 
-            // wrap up data for handlers
-            let (url, text, depth) = fetched.unwrap();
-            let doc = Html::parse_document(&text);
-            let page = Page {
-                url,
-                text,
-                doc,
-                depth,
+            // run handlers on the pages whose turn has come, in the order received
+            let mut closures = Closures {
+                handlers: &mut self.handlers,
+                propagators: &mut self.propagators,
+                sitemap: self.sitemap.as_mut(),
             };
+            let client = &self.client;
+            let handled: Vec<Handled> = pipeline::block_in_place(|| {
+                parser
+                    .take_ready()
+                    .into_iter()
+                    .map(|parsed| pipeline::run(&mut closures, client, parsed, &options))
+                    .collect()
+            });
+            self.stats.update(|stats| stats.pages += handled.len());
 
-            self.do_handlers(&page)?;
+            for page in handled {
+                if let Some(graph) = self.link_graph.as_mut() {
+                    for link in page.links.iter() {
+                        let element = link.element.as_deref();
+                        let attribute = link.attribute.as_deref();
+                        graph.add_edge(&page.url, &link.url, element, attribute);
+                    }
+                }
+                if let Some(check) = self.link_check.as_mut() {
+                    for link in page.links.iter() {
+                        check.refer(&link.url, &page.url);
+                    }
+                }
 
-            if depth < self.depth {
-                self.do_propagators(&page, &mut queue)?;
+                if let Some(inventory) = self.inventory.as_mut() {
+                    page.forms
+                        .iter()
+                        .for_each(|form| inventory.add_form(&page.url, form));
+                }
+
+                if page.depth < self.depth {
+                    self.enqueue(&page, &mut queue);
+                    if self.submit_forms && !(self.respect_robots && page.robots.nofollow) {
+                        self.do_forms(&page, &mut queue);
+                    }
+                } else {
+                    for link in page.links.iter() {
+                        self.check_or_skip(&link.url, &page, SkipReason::Depth, &mut queue);
+                    }
+                }
             }
         }
 
+        if let (Some(jar), Some(path)) = (&self.cookie_jar, &self.cookie_file) {
+            jar.save(path)?;
+        }
+
+        self.summary = self.stats.finish();
+        let event = CrawlEvent::Finish {
+            summary: &self.summary,
+        };
+        emit(&mut self.listeners, event);
+
         Ok(errors)
     }
 
-    fn do_propagators(&mut self, page: &Page, queue: &mut VecDeque<(Url, usize)>) -> Result<()> {
-        let wl = &self.whitelist;
-        let bl = &self.blacklist;
-        let visited = &mut self.visited;
-        let revisit = self.revisit;
-
-        for (kind, props) in self.propagators.iter_mut() {
-            match kind {
-                HandlerEvent::OnSelector(sel) => {
-                    if let Ok(sel) = Selector::parse(sel) {
-                        props.iter_mut().for_each(|propagator| {
-                            page.doc.select(&sel).for_each(|el| {
-                                propagator(&HandlerArgs {
-                                    page,
-                                    element: Some(el),
-                                    client: self.client.clone(),
-                                })
-                                .iter()
-                                .filter(|u| {
-                                    is_allowed(u, wl, bl) && (revisit || !is_visited(u, visited))
-                                })
-                                .for_each(|u| {
-                                    queue.push_back((u.clone(), page.depth + 1));
-                                });
-                            });
-                        });
-                    } else {
-                        bail!("invalid selector {}", sel);
-                    }
+    /// Live counters, get the handle before crawling to watch progress from another task
+    pub fn stats(&self) -> CrawlStats {
+        self.stats.clone()
+    }
+
+    /// Counts from the last crawl
+    pub fn summary(&self) -> &CrawlSummary {
+        &self.summary
+    }
+
+    /// Request and failure counts for each proxy
+    pub fn proxy_health(&self) -> Vec<ProxyHealth> {
+        self.courier.pool.health()
+    }
+
+    /// Cookie jar in use, if any
+    pub fn cookie_jar(&self) -> Option<&Arc<CookieJar>> {
+        self.cookie_jar.as_ref()
+    }
+
+    /// Endpoints recorded so far, None unless enabled with `CrawlerBuilder::inventory`
+    pub fn inventory(&self) -> Option<&Inventory> {
+        self.inventory.as_ref()
+    }
+
+    /// Links found so far, None unless enabled with `CrawlerBuilder::link_graph`
+    pub fn link_graph(&self) -> Option<&LinkGraph> {
+        self.link_graph.as_ref()
+    }
+
+    /// Status of every link found so far, None unless enabled with `CrawlerBuilder::link_check`
+    pub fn link_check(&self) -> Option<&LinkCheck> {
+        self.link_check.as_ref()
+    }
+
+    /// Pages to list in a sitemap so far, None unless enabled with `CrawlerBuilder::sitemap`
+    pub fn sitemap(&self) -> Option<&Sitemap> {
+        self.sitemap.as_ref()
+    }
+
+    /// Record a fetch result, returning the page if it should be parsed
+    async fn receive(
+        &mut self,
+        fetched: Result<Fetched, Failed>,
+        queue: &mut VecDeque<Job>,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> Option<Fetched> {
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(failed) => {
+                self.stats.record_latency(failed.elapsed);
+                self.stats.update(|stats| {
+                    stats.failed += 1;
+                    *stats
+                        .errors
+                        .entry(error_kind(&failed.error).into())
+                        .or_default() += 1;
+                });
+                let event = CrawlEvent::FetchError {
+                    url: &failed.url,
+                    error: &failed.error,
+                    elapsed: failed.elapsed,
+                };
+                emit(&mut self.listeners, event);
+                // form submissions aren't links
+                if let Some(check) = self.link_check.as_mut().filter(|_| !failed.submit) {
+                    check.fail(&failed.url, &failed.error);
                 }
-                HandlerEvent::OnPage => {
-                    props.iter_mut().for_each(|propagator| {
-                        propagator(&HandlerArgs {
-                            page,
-                            element: None,
-                            client: self.client.clone(),
-                        })
-                        .iter()
-                        .filter(|u| is_allowed(u, wl, bl) && (revisit || !is_visited(u, visited)))
-                        .for_each(|u| {
-                            queue.push_back((u.clone(), page.depth + 1));
-                        });
-                    });
+                errors.push(failed.error);
+                return None;
+            }
+        };
+        self.stats.record_latency(fetched.elapsed);
+        self.stats.update(|stats| {
+            stats.bytes += fetched.text.len();
+            *stats.statuses.entry(fetched.status.as_u16()).or_default() += 1;
+            let host = fetched.final_url.host_str().unwrap_or_default();
+            *stats.hosts.entry(host.to_string()).or_default() += 1;
+        });
+        let event = CrawlEvent::Response {
+            url: &fetched.job.url,
+            final_url: &fetched.final_url,
+            depth: fetched.job.depth,
+            status: fetched.status,
+            headers: &fetched.headers,
+            bytes: fetched.text.len(),
+            elapsed: fetched.elapsed,
+        };
+        emit(&mut self.listeners, event);
+
+        if let Some(check) = self.link_check.as_mut().filter(|_| !fetched.job.submit) {
+            let redirect = fetched.redirects.last().map(|r| &r.to);
+            check.record(&fetched.job.url, fetched.status.as_u16(), redirect);
+        }
+        if fetched.job.check {
+            return None;
+        }
+        // links straight to where a redirect landed are already fetched
+        if !self.revisit && !fetched.redirects.is_empty() {
+            is_visited(&fetched.final_url, &mut self.visited);
+        }
+
+        // log in again and retry once if the session was lost
+        if let Some(login) = &self.login {
+            let logged_out = login.is_logged_out(&fetched.final_url)
+                || fetched.redirects.iter().any(|r| login.is_logged_out(&r.to));
+            if !fetched.job.reauth && logged_out {
+                if let Err(login_err) = login.login(&self.courier).await {
+                    errors.push(login_err);
+                    return None;
                 }
+                let mut job = fetched.job;
+                job.reauth = true;
+                queue.push_back(job);
+                return None;
             }
         }
-        Ok(())
-    }
-
-    fn do_handlers(&mut self, page: &Page) -> Result<()> {
-        for (kind, handlers) in self.handlers.iter_mut() {
-            match kind {
-                HandlerEvent::OnSelector(sel) => {
-                    if let Ok(sel) = Selector::parse(sel) {
-                        handlers.iter_mut().for_each(|handler| {
-                            page.doc.select(&sel).for_each(|el| {
-                                handler(&HandlerArgs {
-                                    page,
-                                    element: Some(el),
-                                    client: self.client.clone(),
-                                });
-                            });
-                        });
-                    } else {
-                        bail!("invalid selector {}", sel);
-                    }
+        Some(fetched)
+    }
+
+    /// Queue a submission of every form on the page
+    fn do_forms(&mut self, page: &Handled, queue: &mut VecDeque<Job>) {
+        for form in page.forms.iter() {
+            let fields = form.fill(&self.form_values);
+            let mut url = form.action.clone();
+            if !is_allowed(&url, &self.whitelist, &self.blacklist) {
+                self.skip(&url, &page.url, SkipReason::Scope);
+                continue;
+            }
+
+            let job = if form.method == Method::POST {
+                let key = format!("POST {url} {fields:?}");
+                if !self.revisit && !self.visited.insert(key) {
+                    self.skip(&url, &page.url, SkipReason::Visited);
+                    continue;
                 }
-                HandlerEvent::OnPage => {
-                    handlers.iter_mut().for_each(|handler| {
-                        handler(&HandlerArgs {
-                            page,
-                            element: None,
-                            client: self.client.clone(),
-                        });
-                    });
+                Job {
+                    form: Some(fields),
+                    submit: true,
+                    ..Job::get(url, page.depth + 1)
                 }
+            } else {
+                if fields.is_empty() {
+                    url.set_query(None);
+                } else {
+                    url.query_pairs_mut().clear().extend_pairs(fields.iter());
+                }
+                if !self.revisit && is_visited(&url, &mut self.visited) {
+                    self.skip(&url, &page.url, SkipReason::Visited);
+                    continue;
+                }
+                Job {
+                    submit: true,
+                    ..Job::get(url, page.depth + 1)
+                }
+            };
+            self.push_job(queue, job, Some(&page.url));
+        }
+    }
+
+    /// Queue links found by propagators that are in scope and unvisited
+    fn enqueue(&mut self, page: &Handled, queue: &mut VecDeque<Job>) {
+        for link in page.links.iter() {
+            let url = &link.url;
+            if !is_allowed(url, &self.whitelist, &self.blacklist) {
+                self.check_or_skip(url, page, SkipReason::Scope, queue);
+                continue;
+            }
+            if self.respect_robots && (page.robots.nofollow || link.nofollow) {
+                self.check_or_skip(url, page, SkipReason::Robots, queue);
+                continue;
+            }
+            if let Some(inventory) = self.inventory.as_mut() {
+                inventory.add_link(&page.url, url);
+            }
+            if self.revisit || !is_visited(url, &mut self.visited) {
+                self.push_job(
+                    queue,
+                    Job::get(url.clone(), page.depth + 1),
+                    Some(&page.url),
+                );
+            } else {
+                self.skip(url, &page.url, SkipReason::Visited);
             }
         }
-        Ok(())
     }
+
+    /// Queue a HEAD request for a link that won't be crawled when checking links, skip it otherwise
+    fn check_or_skip(
+        &mut self,
+        url: &Url,
+        page: &Handled,
+        reason: SkipReason,
+        queue: &mut VecDeque<Job>,
+    ) {
+        let Some(check) = self.link_check.as_ref() else {
+            return self.skip(url, &page.url, reason);
+        };
+        if check.checked(url) || is_visited(url, &mut self.visited) {
+            self.skip(url, &page.url, SkipReason::Visited);
+        } else {
+            self.push_job(
+                queue,
+                Job::head(url.clone(), page.depth + 1),
+                Some(&page.url),
+            );
+        }
+    }
+
+    fn push_job(&mut self, queue: &mut VecDeque<Job>, mut job: Job, referrer: Option<&Url>) {
+        job.referrer = referrer.cloned();
+        self.stats.update(|stats| {
+            stats.enqueued += 1;
+            stats.queued = queue.len() + 1;
+        });
+        let event = CrawlEvent::Enqueue {
+            url: &job.url,
+            referrer,
+            depth: job.depth,
+        };
+        emit(&mut self.listeners, event);
+        queue.push_back(job);
+    }
+
+    fn skip(&mut self, url: &Url, referrer: &Url, reason: SkipReason) {
+        self.stats.update(|stats| stats.skipped += 1);
+        let event = CrawlEvent::Skip {
+            url,
+            referrer,
+            reason,
+        };
+        emit(&mut self.listeners, event);
+    }
+}
+
+fn emit(listeners: &mut [Listener], event: CrawlEvent) {
+    listeners.iter_mut().for_each(|listener| listener(&event));
 }
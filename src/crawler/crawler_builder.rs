@@ -1,42 +1,130 @@
 use crate::absolute_url;
+use crate::auxiliary::load_certificate;
+use crate::cookies::CookieJar;
+use crate::profile::{HeaderProfile, Rotation};
+use crate::proxy::{ProxyConfig, MAX_PROXY_FAILURES, PROXY_COOLDOWN};
+
+use super::auth::{Auth, FormLogin, LOGOUT_PATTERNS};
+use super::event::*;
+use super::filter::Filter;
+use super::linkfinder::extract_js_urls;
+use super::redirect::RedirectPolicy;
 
 use super::crawler::*;
 use super::handler::*;
-use anyhow::Result;
-use reqwest::{Client, Url};
+use anyhow::{bail, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::tls::Version;
+use reqwest::{Client, ClientBuilder, Identity, Request, Url};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::fs::read;
 use std::marker::Send;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Client settings are replayed on the client for every proxy in the pool
+pub type ClientSetting = Box<dyn Fn(ClientBuilder) -> Result<ClientBuilder> + Send + Sync>;
 
-/// Builder object for Crawler, fields are left public
+/// Builder object for Crawler, fields are left public  
+/// Changes made directly to `client_builder` only apply to the first proxy's client,
+/// which handlers also get, use `client_setting` to configure every client.
+/// Redirects are followed by the crawler according to `redirect_policy`
 pub struct CrawlerBuilder<'a> {
     pub client_builder: reqwest::ClientBuilder,
-    pub handlers: HashMap<HandlerEvent, Vec<Handler<'a>>>,
-    pub propagators: HashMap<HandlerEvent, Vec<Propagator<'a>>>,
+    pub client_settings: Vec<ClientSetting>,
+    pub proxies: Vec<ProxyConfig>,
+    pub proxy_max_failures: usize,
+    pub proxy_cooldown: Duration,
+    pub handlers: HashMap<HandlerEvent, Vec<Filtered<Handler<'a>>>>,
+    pub propagators: HashMap<HandlerEvent, Vec<Filtered<Propagator<'a>>>>,
+    /// Handlers ( false ) and propagators ( true ) that `when` applies to
+    pub last_added: Vec<(HandlerEvent, bool)>,
+    /// Selectors that failed to compile, with the reason
+    pub invalid_selectors: Vec<(String, String)>,
+    pub listeners: Vec<Listener<'a>>,
     pub depth: usize,
+    pub max_pages: Option<usize>,
     pub workers: usize,
+    pub parse_workers: usize,
     pub blacklist: Vec<String>,
     pub whitelist: Vec<String>,
     pub revisit: bool,
+    pub submit_forms: bool,
+    pub respect_robots: bool,
+    pub report_depth_skips: bool,
+    pub form_values: HashMap<String, String>,
+    pub inventory: bool,
+    pub link_graph: bool,
+    pub link_check: bool,
+    pub sitemap: bool,
+    pub cookie_jar: Option<Arc<CookieJar>>,
+    pub cookie_file: Option<String>,
+    pub auth: Option<Auth>,
+    pub auth_hosts: Vec<String>,
+    pub login: Option<FormLogin>,
+    pub headers: HeaderMap,
+    pub profiles: Vec<HeaderProfile>,
+    pub profile_rotation: Rotation,
+    pub request_hooks: Vec<RequestHook>,
+    pub redirect_policy: RedirectPolicy,
 }
 
 impl<'a> CrawlerBuilder<'a> {
     pub fn new() -> Self {
         Self {
             client_builder: Client::builder(),
+            client_settings: vec![],
+            proxies: vec![],
+            proxy_max_failures: MAX_PROXY_FAILURES,
+            proxy_cooldown: PROXY_COOLDOWN,
             handlers: HashMap::new(),
             propagators: HashMap::new(),
+            last_added: vec![],
+            invalid_selectors: vec![],
+            listeners: vec![],
             depth: 2,
+            max_pages: None,
             workers: 40,
+            parse_workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
             whitelist: vec![],
             blacklist: vec![],
             revisit: false,
+            submit_forms: false,
+            respect_robots: false,
+            report_depth_skips: false,
+            form_values: HashMap::new(),
+            inventory: false,
+            link_graph: false,
+            link_check: false,
+            sitemap: false,
+            cookie_jar: None,
+            cookie_file: None,
+            auth: None,
+            auth_hosts: vec![],
+            login: None,
+            headers: HeaderMap::new(),
+            profiles: vec![],
+            profile_rotation: Rotation::PerRequest,
+            request_hooks: vec![],
+            redirect_policy: RedirectPolicy::default(),
         }
     }
 
-    /// Consume the Builder and produce a Crawler
+    /// Consume the Builder and produce a Crawler  
+    /// Fails with `InvalidSelectors` if any handler or propagator selector is invalid
     pub fn build(self) -> Result<Crawler<'a>> {
+        if !self.invalid_selectors.is_empty() {
+            return Err(InvalidSelectors(self.invalid_selectors).into());
+        }
+        let handler_filters = self.handlers.values().flatten().map(|h| &h.filters);
+        let propagator_filters = self.propagators.values().flatten().map(|p| &p.filters);
+        if let Some(err) = handler_filters
+            .chain(propagator_filters)
+            .flatten()
+            .find_map(|filter| filter.error.as_ref())
+        {
+            bail!("{err}");
+        }
         Crawler::from_builder(self)
     }
 
@@ -64,49 +152,283 @@ impl<'a> CrawlerBuilder<'a> {
         self
     }
 
+    /// Submit forms found on pages and crawl the results ( default: false )
+    pub fn submit_forms(mut self, submit: bool) -> Self {
+        self.submit_forms = submit;
+        self
+    }
+
+    /// Don't follow `rel="nofollow"` links or links on pages marked nofollow by
+    /// `<meta name="robots">` or `X-Robots-Tag` ( default: false )  
+    /// They are skipped with `SkipReason::Robots`, noindex and noarchive are left to handlers in `Page::robots`
+    pub fn respect_robots(mut self, respect: bool) -> Self {
+        self.respect_robots = respect;
+        self
+    }
+
+    /// Fill form fields named `name` with `value` when submitting forms  
+    /// Other fields use their default value or a placeholder for their type
+    pub fn form_value(mut self, name: &str, value: &str) -> Self {
+        self.form_values.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Record query params, form fields and referrers of discovered endpoints ( default: false )  
+    /// Read the results with `Crawler::inventory()` after crawling
+    pub fn inventory(mut self, inventory: bool) -> Self {
+        self.inventory = inventory;
+        self
+    }
+
+    /// Record every link propagators return, with the element and attribute it came from ( default: false )  
+    /// Read the results with `Crawler::link_graph()` after crawling
+    pub fn link_graph(mut self, link_graph: bool) -> Self {
+        self.link_graph = link_graph;
+        self
+    }
+
+    /// Check every link propagators return ( default: false )  
+    /// Links out of scope or past the depth limit get a HEAD request and are not crawled  
+    /// Read the results with `Crawler::link_check()` after crawling
+    pub fn link_check(mut self, link_check: bool) -> Self {
+        self.link_check = link_check;
+        self
+    }
+
+    /// Collect 2xx HTML pages on the start url's host that are their own canonical and not noindex
+    /// for a sitemap ( default: false )  
+    /// Read the results with `Crawler::sitemap()` after crawling
+    pub fn sitemap(mut self, sitemap: bool) -> Self {
+        self.sitemap = sitemap;
+        self
+    }
+
+    /// Set which redirects to follow ( default: Limited(10) )  
+    /// The chain is recorded in `Page::redirects`
+    pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+
+    /// Stop requesting pages after this many ( default: unlimited )  
+    /// Link check HEAD requests don't count, later pages are skipped with `SkipReason::MaxPages`
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
     /// Set the concurrency limit ( default: 40 )
     pub fn workers(mut self, limit: usize) -> Self {
         self.workers = limit;
         self
     }
+
+    /// Set the number of threads parsing pages ( default: number of CPUs )  
+    /// The crawl keeps fetching while they parse, 1 parses each page as it arrives  
+    /// Handlers still run on one page at a time, in the order pages arrive
+    pub fn parse_workers(mut self, threads: usize) -> Self {
+        self.parse_workers = threads;
+        self
+    }
+    /// Configure every reqwest client the crawler builds
+    /// Closure type: `Fn(ClientBuilder) -> Result<ClientBuilder>`
+    pub fn client_setting<F>(mut self, closure: F) -> Self
+    where
+        F: Fn(ClientBuilder) -> Result<ClientBuilder> + Send + Sync + 'static,
+    {
+        self.client_settings.push(Box::new(closure));
+        self
+    }
+
     /// Set the user agent
-    pub fn user_agent(mut self, user_agent: &'a str) -> Self {
-        self.client_builder = self.client_builder.user_agent(user_agent.to_string());
+    pub fn user_agent(self, user_agent: &'a str) -> Self {
+        let user_agent = user_agent.to_string();
+        self.client_setting(move |cb| Ok(cb.user_agent(user_agent.clone())))
+    }
+
+    /// Add a header profile, requests rotate through all added profiles  
+    /// Headers set with `header` take precedence over profile headers
+    pub fn header_profile(mut self, profile: HeaderProfile) -> Self {
+        self.profiles.push(profile);
+        self
+    }
+
+    /// Switch profiles per request or per host ( default: PerRequest )
+    pub fn profile_rotation(mut self, rotation: Rotation) -> Self {
+        self.profile_rotation = rotation;
+        self
+    }
+
+    /// Send a header with every request
+    pub fn header(mut self, name: &str, value: &str) -> Result<Self> {
+        self.headers.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+        Ok(self)
+    }
+
+    /// Add a hook that modifies every request right before it is sent  
+    /// Closure type: `Fn(&mut Request)`  
+    pub fn before_request<F>(mut self, closure: F) -> Self
+    where
+        F: Fn(&mut Request) + Send + Sync + 'static,
+    {
+        self.request_hooks.push(Arc::new(closure));
         self
     }
 
-    /// Set an https proxy with a cacert.der file
-    pub fn proxy(mut self, proxy_str: &str, ca_cert: &str) -> Result<Self> {
-        let mut buf = Vec::new();
-        File::open(ca_cert)?.read_to_end(&mut buf)?;
-        let cert = reqwest::Certificate::from_der(&buf)?;
+    /// Set an https proxy with a PEM or DER CA certificate
+    pub fn proxy(self, proxy_str: &str, ca_cert: &str) -> Result<Self> {
+        load_certificate(ca_cert)?;
+        Ok(self.add_proxy(ProxyConfig::new(proxy_str).ca_cert(ca_cert)))
+    }
+
+    /// Add a proxy, requests rotate through all added proxies
+    pub fn add_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
 
-        let proxy = reqwest::Proxy::all(proxy_str)?;
+    /// Skip a proxy after this many consecutive connect or proxy errors ( default: 3 )
+    pub fn proxy_max_failures(mut self, failures: usize) -> Self {
+        self.proxy_max_failures = failures;
+        self
+    }
 
-        self.client_builder = self.client_builder.add_root_certificate(cert).proxy(proxy);
+    /// Try a skipped proxy again after this long ( default: 30s )
+    pub fn proxy_cooldown(mut self, cooldown: Duration) -> Self {
+        self.proxy_cooldown = cooldown;
+        self
+    }
+
+    /// Use a cookie jar, updated from `Set-Cookie` headers during the crawl
+    pub fn cookie_jar(mut self, jar: Arc<CookieJar>) -> Self {
+        self.cookie_jar = Some(jar);
+        self
+    }
+
+    /// Load cookies from a Netscape cookies.txt file and save them back after crawling  
+    /// The file is created if it doesn't exist, its cookies are added to any already set
+    pub fn cookie_file(mut self, path: &str) -> Result<Self> {
+        let loaded = CookieJar::load(path)?;
+        match &self.cookie_jar {
+            Some(jar) => loaded
+                .all()
+                .into_iter()
+                .for_each(|cookie| jar.insert(cookie)),
+            None => self.cookie_jar = Some(Arc::new(loaded)),
+        }
+        self.cookie_file = Some(path.to_string());
         Ok(self)
     }
 
-    /// Set the request timeout
-    pub fn timeout(mut self, seconds: u64, nanoseconds: u32) -> Self {
-        self.client_builder = self
-            .client_builder
-            .timeout(std::time::Duration::new(seconds, nanoseconds));
+    /// Add cookies from a raw `Cookie` header, sent to the host of `url`
+    pub fn cookie_header(mut self, url: &str, header: &str) -> Result<Self> {
+        let url = Url::parse(url)?;
+        self.cookie_jar
+            .get_or_insert_with(Default::default)
+            .add_cookie_header(header, &url);
+        Ok(self)
+    }
+
+    /// Send credentials with requests to the start url's origin, or the hosts set with `auth_host`
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
         self
     }
 
+    /// Send credentials to this host instead of the start url's origin ( repeatable )
+    pub fn auth_host(mut self, host: &str) -> Self {
+        self.auth_hosts.push(host.to_string());
+        self
+    }
+
+    /// Use HTTP basic auth
+    pub fn basic_auth(self, username: &str, password: Option<&str>) -> Self {
+        self.auth(Auth::Basic {
+            username: username.to_string(),
+            password: password.map(|p| p.to_string()),
+        })
+    }
+
+    /// Use HTTP digest auth
+    pub fn digest_auth(self, username: &str, password: &str) -> Self {
+        self.auth(Auth::Digest {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    /// Send an `Authorization: Bearer` token
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.auth(Auth::Bearer(token.to_string()))
+    }
+
+    /// Submit a login form before crawling, and again when the session is lost  
+    /// A cookie jar is created if none was set
+    pub fn form_login(mut self, login: FormLogin) -> Self {
+        self.login = Some(login);
+        self
+    }
+
+    /// Don't crawl urls that look like logout links ( see `LOGOUT_PATTERNS` )
+    pub fn logout_guard(mut self) -> Self {
+        for expr in LOGOUT_PATTERNS {
+            self = self.blacklist(expr);
+        }
+        self
+    }
+
+    /// Accept invalid and self-signed certificates ( default: false )  
+    /// WARNING: this makes connections vulnerable to interception
+    pub fn danger_accept_invalid_certs(self, accept: bool) -> Self {
+        self.client_setting(move |cb| Ok(cb.danger_accept_invalid_certs(accept)))
+    }
+
+    /// Trust an extra root certificate, PEM or DER
+    pub fn root_certificate(self, path: &str) -> Result<Self> {
+        let cert = load_certificate(path)?;
+        Ok(self.client_setting(move |cb| Ok(cb.add_root_certificate(cert.clone()))))
+    }
+
+    /// Only trust certificates added with `root_certificate` ( default: false )  
+    /// With native-tls this drops the system store, openssl still reads `SSL_CERT_FILE`/`SSL_CERT_DIR` when unpinned
+    pub fn pin_root_certificates(self, pin: bool) -> Self {
+        self.client_setting(move |cb| Ok(cb.tls_built_in_root_certs(!pin)))
+    }
+
+    /// Present a client certificate from PEM certificate and PKCS#8 key files
+    pub fn client_identity_pem(self, cert_path: &str, key_path: &str) -> Result<Self> {
+        let identity = Identity::from_pkcs8_pem(&read(cert_path)?, &read(key_path)?)?;
+        Ok(self.client_setting(move |cb| Ok(cb.identity(identity.clone()))))
+    }
+
+    /// Present a client certificate from a PKCS#12 file
+    pub fn client_identity_pkcs12(self, path: &str, password: &str) -> Result<Self> {
+        let identity = Identity::from_pkcs12_der(&read(path)?, password)?;
+        Ok(self.client_setting(move |cb| Ok(cb.identity(identity.clone()))))
+    }
+
+    /// Set the minimum TLS version  
+    /// native-tls has no TLS 1.3 minimum, building the crawler fails if asked for one
+    pub fn min_tls_version(self, version: Version) -> Self {
+        self.client_setting(move |cb| Ok(cb.min_tls_version(version)))
+    }
+
+    /// Set the request timeout
+    pub fn timeout(self, seconds: u64, nanoseconds: u32) -> Self {
+        let timeout = Duration::new(seconds, nanoseconds);
+        self.client_setting(move |cb| Ok(cb.timeout(timeout)))
+    }
+
     /// Add a handler  
     /// Closure type: `FnMut(&HandlerArgs)`  
     pub fn on_page<F>(mut self, closure: F) -> Self
     where
         F: FnMut(&HandlerArgs) + Send + Sync + 'a,
     {
-        let closure: Box<dyn FnMut(&HandlerArgs) + Send + Sync + 'a> = Box::new(closure);
-        if let Some(handlers) = self.handlers.get_mut(&HandlerEvent::OnPage) {
-            handlers.push(closure)
-        } else {
-            self.handlers.insert(HandlerEvent::OnPage, vec![closure]);
-        }
+        self.push_handler(HandlerEvent::OnPage, Box::new(closure));
         self
     }
 
@@ -116,12 +438,7 @@ impl<'a> CrawlerBuilder<'a> {
     where
         F: FnMut(&HandlerArgs) -> Vec<Url> + Send + Sync + 'a,
     {
-        let closure: Propagator = Box::new(closure);
-        if let Some(propagators) = self.propagators.get_mut(&HandlerEvent::OnPage) {
-            propagators.push(closure)
-        } else {
-            self.propagators.insert(HandlerEvent::OnPage, vec![closure]);
-        }
+        self.push_propagator(HandlerEvent::OnPage, Box::new(closure));
         self
     }
 
@@ -131,16 +448,8 @@ impl<'a> CrawlerBuilder<'a> {
     where
         F: FnMut(&HandlerArgs) + Send + Sync + 'a,
     {
-        let sel = sel.to_string();
-        let closure: Box<dyn FnMut(&HandlerArgs) + Send + Sync + 'a> = Box::new(closure);
-        if let Some(handlers) = self
-            .handlers
-            .get_mut(&HandlerEvent::OnSelector(sel.clone()))
-        {
-            handlers.push(closure)
-        } else {
-            self.handlers
-                .insert(HandlerEvent::OnSelector(sel), vec![closure]);
+        if let Some(event) = self.compile(sel) {
+            self.push_handler(event, Box::new(closure));
         }
         self
     }
@@ -151,21 +460,136 @@ impl<'a> CrawlerBuilder<'a> {
     where
         F: FnMut(&HandlerArgs) -> Vec<Url> + 'a + Send + Sync,
     {
-        let sel = sel.to_string();
-        let closure: Box<dyn FnMut(&HandlerArgs) -> Vec<Url> + Send + Sync + 'a> =
-            Box::new(closure);
-        if let Some(propagators) = self
-            .propagators
-            .get_mut(&HandlerEvent::OnSelector(sel.clone()))
-        {
-            propagators.push(closure)
-        } else {
-            self.propagators
-                .insert(HandlerEvent::OnSelector(sel), vec![closure]);
+        if let Some(event) = self.compile(sel) {
+            self.push_propagator(event, Box::new(closure));
         }
         self
     }
 
+    /// Send `SkipReason::Depth` skips to listeners ( default: false )  
+    /// Propagators then also run on pages at the maximum depth to find the links
+    pub fn report_depth_skips(mut self, report: bool) -> Self {
+        self.report_depth_skips = report;
+        self
+    }
+
+    /// Listen to every lifecycle event  
+    /// Closure type: `FnMut(&CrawlEvent)`  
+    pub fn on_event<F>(mut self, closure: F) -> Self
+    where
+        F: FnMut(&CrawlEvent) + Send + Sync + 'a,
+    {
+        self.listeners.push(Box::new(closure));
+        self
+    }
+
+    /// Called with the start url when the crawl starts  
+    /// Closure type: `FnMut(&Url)`  
+    pub fn on_start<F>(self, mut closure: F) -> Self
+    where
+        F: FnMut(&Url) + Send + Sync + 'a,
+    {
+        self.on_event(move |event| {
+            if let CrawlEvent::Start { url } = event {
+                closure(url)
+            }
+        })
+    }
+
+    /// Called when a url is queued, with the page it was found on  
+    /// Closure type: `FnMut(&Url, Option<&Url>)`  
+    pub fn on_enqueue<F>(self, mut closure: F) -> Self
+    where
+        F: FnMut(&Url, Option<&Url>) + Send + Sync + 'a,
+    {
+        self.on_event(move |event| {
+            if let CrawlEvent::Enqueue { url, referrer, .. } = event {
+                closure(url, *referrer)
+            }
+        })
+    }
+
+    /// Called when a url is not queued, with the page it was found on and why  
+    /// Closure type: `FnMut(&Url, &Url, SkipReason)`  
+    pub fn on_skip<F>(self, mut closure: F) -> Self
+    where
+        F: FnMut(&Url, &Url, SkipReason) + Send + Sync + 'a,
+    {
+        self.on_event(move |event| {
+            if let CrawlEvent::Skip {
+                url,
+                referrer,
+                reason,
+            } = event
+            {
+                closure(url, referrer, *reason)
+            }
+        })
+    }
+
+    /// Called with summary counts when the crawl is done  
+    /// Closure type: `FnMut(&CrawlSummary)`  
+    pub fn on_finish<F>(self, mut closure: F) -> Self
+    where
+        F: FnMut(&CrawlSummary) + Send + Sync + 'a,
+    {
+        self.on_event(move |event| {
+            if let CrawlEvent::Finish { summary } = event {
+                closure(summary)
+            }
+        })
+    }
+
+    /// Only run the handlers or propagators added by the previous call on pages passing `filter`  
+    /// Chain to require several filters, e.g. `.add_handler("a", f).when(url_matches("/blog/"))`
+    pub fn when(mut self, filter: Filter) -> Self {
+        for (event, propagator) in self.last_added.iter() {
+            let filters = if *propagator {
+                self.propagators
+                    .get_mut(event)
+                    .and_then(|props| props.last_mut())
+                    .map(|prop| &mut prop.filters)
+            } else {
+                self.handlers
+                    .get_mut(event)
+                    .and_then(|handlers| handlers.last_mut())
+                    .map(|handler| &mut handler.filters)
+            };
+            if let Some(filters) = filters {
+                filters.push(filter.clone());
+            }
+        }
+        self
+    }
+
+    /// compile a selector, recording it for `build` to report if invalid
+    fn compile(&mut self, sel: &str) -> Option<HandlerEvent> {
+        match CssSelector::parse(sel) {
+            Ok(sel) => Some(HandlerEvent::OnSelector(sel)),
+            Err(reason) => {
+                self.invalid_selectors.push((sel.to_string(), reason));
+                self.last_added.clear();
+                None
+            }
+        }
+    }
+
+    fn push_handler(&mut self, event: HandlerEvent, closure: Handler<'a>) {
+        self.handlers
+            .entry(event.clone())
+            .or_default()
+            .push(Filtered::new(closure));
+        self.last_added = vec![(event, false)];
+    }
+
+    fn push_propagator(&mut self, event: HandlerEvent, closure: Propagator<'a>) {
+        self.propagators
+            .entry(event.clone())
+            .or_default()
+            .push(Filtered::new(closure));
+        self.last_added = vec![(event, true)];
+    }
+
     /// Propagate on all href and src attributes  
     /// NOTE: "scheme://domain.tld/path" and "scheme://domain.tld/path/" may behave differently,  
     /// see <https://docs.rs/reqwest/0.10.8/reqwest/struct.Url.html#method.join> for info.
@@ -189,7 +613,33 @@ impl<'a> CrawlerBuilder<'a> {
         };
 
         self = self.add_propagator("*[href]", href_prop);
+        let added = self.last_added.clone();
         self = self.add_propagator("*[src]", src_prop);
+        self.last_added.extend(added);
+
+        self
+    }
+
+    /// Propagate on urls found in inline scripts and fetched `.js` files  
+    /// Script sources are found with regex heuristics, so expect some false positives
+    pub fn add_js_propagators(mut self) -> Self {
+        let inline_prop = |args: &HandlerArgs| -> Vec<Url> {
+            let source = args.element.unwrap().text().collect::<String>();
+            extract_js_urls(&args.page.url, &source)
+        };
+
+        let file_prop = |args: &HandlerArgs| -> Vec<Url> {
+            if args.page.url.path().ends_with(".js") {
+                extract_js_urls(&args.page.url, &args.page.text)
+            } else {
+                vec![]
+            }
+        };
+
+        self = self.add_propagator("script:not([src])", inline_prop);
+        let added = self.last_added.clone();
+        self = self.on_page_propagator(file_prop);
+        self.last_added.extend(added);
 
         self
     }
@@ -0,0 +1,100 @@
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode, Url};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Listeners are called with every lifecycle event
+pub type Listener<'a> = Box<dyn FnMut(&CrawlEvent) + Send + Sync + 'a>;
+
+/// Why a url was not queued
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum SkipReason {
+    /// Outside the whitelist or inside the blacklist
+    Scope,
+    /// Already visited
+    Visited,
+    /// Disallowed by robots rules
+    Robots,
+    /// Found on a page at the maximum depth, only with `report_depth_skips`
+    Depth,
+    /// Queued after `max_pages` pages were requested, `referrer` is the url itself for the start url
+    MaxPages,
+}
+
+/// Counts for a crawl, see `CrawlStats`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CrawlSummary {
+    /// Urls queued, including the start url
+    pub enqueued: usize,
+    /// Urls not queued
+    pub skipped: usize,
+    /// Urls waiting in the queue
+    pub queued: usize,
+    /// Requests being fetched
+    pub in_flight: usize,
+    /// Pages fetched and handled
+    pub pages: usize,
+    /// Failed fetches
+    pub failed: usize,
+    /// Failed fetches by kind, such as `timeout` or `connect`
+    pub errors: BTreeMap<String, usize>,
+    /// Response body bytes received
+    pub bytes: usize,
+    /// Responses per host
+    pub hosts: BTreeMap<String, usize>,
+    /// Responses per status code
+    pub statuses: BTreeMap<u16, usize>,
+    /// Fetches per latency bucket, keyed by upper bound in ms, `u64::MAX` for slower ones
+    pub latency: BTreeMap<u64, usize>,
+    /// Total time spent fetching
+    pub latency_total: Duration,
+    /// Time since the crawl started
+    pub elapsed: Duration,
+}
+
+impl CrawlSummary {
+    /// Requests completed per second, failed or not
+    pub fn requests_per_sec(&self) -> f64 {
+        let done = self.statuses.values().sum::<usize>() + self.failed;
+        done as f64 / self.elapsed.max(Duration::from_millis(1)).as_secs_f64()
+    }
+}
+
+/// Stages of a crawl you can listen to, see `CrawlerBuilder::on_event`
+pub enum CrawlEvent<'e> {
+    /// The crawl is starting at `url`
+    Start { url: &'e Url },
+    /// `url` was queued, `referrer` is the page it was found on
+    Enqueue {
+        url: &'e Url,
+        referrer: Option<&'e Url>,
+        depth: usize,
+    },
+    /// `url` was found on `referrer` but not queued
+    Skip {
+        url: &'e Url,
+        referrer: &'e Url,
+        reason: SkipReason,
+    },
+    /// `url` was handed to a worker to fetch
+    Request { url: &'e Url, method: &'e Method },
+    /// A response arrived for `url`, before it is parsed
+    /// `elapsed` is the time taken to fetch, including redirects
+    Response {
+        url: &'e Url,
+        final_url: &'e Url,
+        depth: usize,
+        status: StatusCode,
+        headers: &'e HeaderMap,
+        bytes: usize,
+        elapsed: Duration,
+    },
+    /// Fetching `url` failed
+    FetchError {
+        url: &'e Url,
+        error: &'e anyhow::Error,
+        elapsed: Duration,
+    },
+    /// The crawl is done
+    Finish { summary: &'e CrawlSummary },
+}
@@ -0,0 +1,100 @@
+use super::handler::Page;
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+use std::ops::{Not, RangeBounds};
+use std::sync::Arc;
+
+/// A condition a page must meet for a handler or propagator to run, see `CrawlerBuilder::when`
+#[derive(Clone)]
+pub struct Filter {
+    predicate: Arc<dyn Fn(&Page) -> bool + Send + Sync>,
+    /// Why the filter could not be created, reported by `CrawlerBuilder::build`
+    pub error: Option<String>,
+}
+
+impl Filter {
+    /// Filter on any condition
+    pub fn new<F>(predicate: F) -> Self
+    where
+        F: Fn(&Page) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            predicate: Arc::new(predicate),
+            error: None,
+        }
+    }
+
+    /// Whether `page` passes the filter
+    pub fn matches(&self, page: &Page) -> bool {
+        (self.predicate)(page)
+    }
+
+    /// Pass pages that pass both filters
+    pub fn and(self, other: Filter) -> Self {
+        let error = self.error.clone().or_else(|| other.error.clone());
+        Self {
+            error,
+            ..Self::new(move |page| self.matches(page) && other.matches(page))
+        }
+    }
+
+    /// Pass pages that pass either filter
+    pub fn or(self, other: Filter) -> Self {
+        let error = self.error.clone().or_else(|| other.error.clone());
+        Self {
+            error,
+            ..Self::new(move |page| self.matches(page) || other.matches(page))
+        }
+    }
+}
+
+/// Pass pages that fail the filter
+impl Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Self {
+        let error = self.error.clone();
+        Self {
+            error,
+            ..Self::new(move |page| !self.matches(page))
+        }
+    }
+}
+
+/// Pages whose url matches the regex `expr`
+pub fn url_matches(expr: &str) -> Filter {
+    match Regex::new(expr) {
+        Ok(re) => Filter::new(move |page| re.is_match(page.url.as_str())),
+        Err(err) => Filter {
+            error: Some(format!("invalid url regex {expr}: {err}")),
+            ..Filter::new(|_| false)
+        },
+    }
+}
+
+/// Pages found at a depth within `range`
+pub fn depth_in<R>(range: R) -> Filter
+where
+    R: RangeBounds<usize> + Send + Sync + 'static,
+{
+    Filter::new(move |page| range.contains(&page.depth))
+}
+
+/// Pages with a response status within `range`, such as `200..300`
+pub fn status_in<R>(range: R) -> Filter
+where
+    R: RangeBounds<u16> + Send + Sync + 'static,
+{
+    Filter::new(move |page| range.contains(&page.status.as_u16()))
+}
+
+/// Pages with a Content-Type header containing `expr`, case insensitive
+pub fn content_type(expr: &str) -> Filter {
+    let expr = expr.to_ascii_lowercase();
+    Filter::new(move |page| {
+        page.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.to_ascii_lowercase().contains(&expr))
+    })
+}
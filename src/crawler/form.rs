@@ -0,0 +1,137 @@
+use crate::absolute_url;
+use reqwest::{Method, Url};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+/// A field of a Form
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormInput {
+    /// Name attribute
+    pub name: String,
+    /// Input type, tag name for `select` and `textarea`
+    pub kind: String,
+    /// Default value if present
+    pub value: Option<String>,
+    /// Checked state for checkboxes and radio buttons
+    pub checked: bool,
+}
+
+/// An HTML form found on a page
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Form {
+    /// Absolute url the form submits to
+    pub action: Url,
+    /// Submission method, GET or POST
+    pub method: Method,
+    /// Named fields, including hidden ones
+    pub inputs: Vec<FormInput>,
+}
+
+impl Form {
+    /// Extract every form from a document
+    pub fn extract(doc: &Html, base_url: &Url) -> Vec<Form> {
+        let sel = Selector::parse("form").unwrap();
+        doc.select(&sel)
+            .filter_map(|el| Self::parse(el, base_url))
+            .collect()
+    }
+
+    /// Parse a `<form>` element, None if it isn't one
+    pub fn parse(element: ElementRef, base_url: &Url) -> Option<Form> {
+        let value = element.value();
+        if value.name() != "form" {
+            return None;
+        }
+
+        let action = match value.attr("action") {
+            Some(action) if !action.trim().is_empty() => {
+                absolute_url(base_url, action.trim()).ok()?
+            }
+            _ => base_url.clone(),
+        };
+
+        let method = match value.attr("method") {
+            Some(method) if method.eq_ignore_ascii_case("post") => Method::POST,
+            _ => Method::GET,
+        };
+
+        let sel = Selector::parse("input[name], select[name], textarea[name]").unwrap();
+        let inputs = element.select(&sel).filter_map(Self::parse_input).collect();
+
+        Some(Form {
+            action,
+            method,
+            inputs,
+        })
+    }
+
+    fn parse_input(el: ElementRef) -> Option<FormInput> {
+        let value = el.value();
+        let name = value.attr("name")?.to_string();
+
+        let (kind, default) = match value.name() {
+            "select" => {
+                let sel = Selector::parse("option").unwrap();
+                let selected = Selector::parse("option[selected]").unwrap();
+                let option = el
+                    .select(&selected)
+                    .next()
+                    .or_else(|| el.select(&sel).next());
+                let default = option.map(|opt| match opt.value().attr("value") {
+                    Some(value) => value.to_string(),
+                    None => opt.text().collect::<String>().trim().to_string(),
+                });
+                ("select".to_string(), default)
+            }
+            "textarea" => (
+                "textarea".to_string(),
+                Some(el.text().collect::<String>()).filter(|text| !text.is_empty()),
+            ),
+            _ => (
+                value.attr("type").unwrap_or("text").to_ascii_lowercase(),
+                value.attr("value").map(|v| v.to_string()),
+            ),
+        };
+
+        Some(FormInput {
+            name,
+            kind,
+            value: default,
+            checked: value.attr("checked").is_some(),
+        })
+    }
+
+    /// Field values to submit, `fill` overrides defaults by field name
+    /// Unchecked checkboxes and radio buttons are left out unless filled
+    pub fn fill(&self, fill: &HashMap<String, String>) -> Vec<(String, String)> {
+        self.inputs
+            .iter()
+            .filter_map(|input| {
+                if let Some(value) = fill.get(&input.name) {
+                    return Some((input.name.clone(), value.clone()));
+                }
+                let value = match input.kind.as_str() {
+                    "checkbox" | "radio" if !input.checked => return None,
+                    "checkbox" | "radio" => input.value.clone().unwrap_or_else(|| "on".into()),
+                    "submit" | "image" | "reset" | "button" | "file" => input.value.clone()?,
+                    kind => input
+                        .value
+                        .clone()
+                        .unwrap_or_else(|| Self::placeholder(kind).to_string()),
+                };
+                Some((input.name.clone(), value))
+            })
+            .collect()
+    }
+
+    fn placeholder(kind: &str) -> &'static str {
+        match kind {
+            "email" => "test@example.com",
+            "number" | "range" => "1",
+            "tel" => "5555555555",
+            "url" => "https://example.com",
+            "date" => "2000-01-01",
+            _ => "test",
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use crate::auxiliary::{csv_field, xml_escape};
+use anyhow::{bail, Result};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A link from one page to another
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub struct Edge {
+    /// Page the link was found on
+    pub source: String,
+    /// Url the link points to
+    pub target: String,
+    /// Tag of the element that produced the link, None for page propagators
+    pub element: Option<String>,
+    /// Attribute holding the link, None if it came from text such as a script
+    pub attribute: Option<String>,
+}
+
+/// Links found by propagators during a crawl, including out of scope targets
+#[derive(Clone, Debug, Default)]
+pub struct LinkGraph {
+    pub edges: BTreeSet<Edge>,
+}
+
+impl LinkGraph {
+    /// Record a link from `source` to `target`
+    pub fn add_edge(
+        &mut self,
+        source: &Url,
+        target: &Url,
+        element: Option<&str>,
+        attribute: Option<&str>,
+    ) {
+        self.edges.insert(Edge {
+            source: source.to_string(),
+            target: target.to_string(),
+            element: element.map(str::to_string),
+            attribute: attribute.map(str::to_string),
+        });
+    }
+
+    /// Every url that is a source or target, in order
+    pub fn nodes(&self) -> BTreeSet<&str> {
+        self.edges
+            .iter()
+            .flat_map(|edge| [edge.source.as_str(), edge.target.as_str()])
+            .collect()
+    }
+
+    /// One JSON object per line
+    pub fn to_jsonl(&self) -> Result<String> {
+        let mut text = String::new();
+        for edge in self.edges.iter() {
+            text.push_str(&serde_json::to_string(edge)?);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    /// CSV with a `source,target,element,attribute` header
+    pub fn to_csv(&self) -> String {
+        let mut text = String::from("source,target,element,attribute\n");
+        for edge in self.edges.iter() {
+            let fields = [
+                edge.source.as_str(),
+                edge.target.as_str(),
+                edge.element.as_deref().unwrap_or_default(),
+                edge.attribute.as_deref().unwrap_or_default(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            text.push_str(&fields.join(","));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// GraphML with the url of each node and the element and attribute of each edge
+    pub fn to_graphml(&self) -> String {
+        let ids: BTreeMap<&str, usize> = self
+            .nodes()
+            .into_iter()
+            .enumerate()
+            .map(|(id, url)| (url, id))
+            .collect();
+
+        let mut text = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"url\" for=\"node\" attr.name=\"url\" attr.type=\"string\"/>\n",
+            "  <key id=\"element\" for=\"edge\" attr.name=\"element\" attr.type=\"string\"/>\n",
+            "  <key id=\"attribute\" for=\"edge\" attr.name=\"attribute\" attr.type=\"string\"/>\n",
+            "  <graph id=\"links\" edgedefault=\"directed\">\n",
+        ));
+        for (url, id) in ids.iter() {
+            text.push_str(&format!(
+                "    <node id=\"n{id}\"><data key=\"url\">{}</data></node>\n",
+                xml_escape(url)
+            ));
+        }
+        for edge in self.edges.iter() {
+            text.push_str(&format!(
+                "    <edge source=\"n{}\" target=\"n{}\">",
+                ids[edge.source.as_str()],
+                ids[edge.target.as_str()]
+            ));
+            if let Some(element) = &edge.element {
+                let element = xml_escape(element);
+                text.push_str(&format!("<data key=\"element\">{element}</data>"));
+            }
+            if let Some(attribute) = &edge.attribute {
+                let attribute = xml_escape(attribute);
+                text.push_str(&format!("<data key=\"attribute\">{attribute}</data>"));
+            }
+            text.push_str("</edge>\n");
+        }
+        text.push_str("  </graph>\n</graphml>\n");
+        text
+    }
+
+    /// Graphviz DOT, edges are labeled `element[attribute]`
+    pub fn to_dot(&self) -> String {
+        let mut text = String::from("digraph links {\n");
+        for edge in self.edges.iter() {
+            let label = match (&edge.element, &edge.attribute) {
+                (Some(element), Some(attribute)) => format!("{element}[{attribute}]"),
+                (Some(element), None) => element.clone(),
+                (None, _) => String::new(),
+            };
+            text.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                dot_escape(&edge.source),
+                dot_escape(&edge.target),
+                dot_escape(&label)
+            ));
+        }
+        text.push_str("}\n");
+        text
+    }
+
+    /// Export by format name: `jsonl`, `csv`, `graphml` or `dot`
+    pub fn export(&self, format: &str) -> Result<String> {
+        Ok(match format {
+            "jsonl" => self.to_jsonl()?,
+            "csv" => self.to_csv(),
+            "graphml" => self.to_graphml(),
+            "dot" => self.to_dot(),
+            other => bail!("unknown graph format: {other}"),
+        })
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
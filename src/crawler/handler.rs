@@ -1,5 +1,12 @@
-use reqwest::{Client, Url};
-use scraper::{ElementRef, Html};
+use super::filter::Filter;
+use super::form::Form;
+use super::redirect::Redirect;
+use super::robots::RobotsDirectives;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Request, StatusCode, Url};
+use scraper::{ElementRef, Html, Selector};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 /// Handlers are void Fns
@@ -8,6 +15,29 @@ pub type Handler<'a> = Box<dyn FnMut(&HandlerArgs) + Send + Sync + 'a>;
 /// Propagators return a `Vec<Url>` to queue
 pub type Propagator<'a> = Box<dyn FnMut(&HandlerArgs) -> Vec<Url> + Send + Sync + 'a>;
 
+/// Request hooks modify every request before it is sent
+pub type RequestHook = Arc<dyn Fn(&mut Request) + Send + Sync>;
+
+/// A handler or propagator with the filters a page must pass to run it
+pub struct Filtered<T> {
+    pub closure: T,
+    pub filters: Vec<Filter>,
+}
+
+impl<T> Filtered<T> {
+    pub fn new(closure: T) -> Self {
+        Self {
+            closure,
+            filters: vec![],
+        }
+    }
+
+    /// Whether `page` passes every filter
+    pub fn accepts(&self, page: &Page) -> bool {
+        self.filters.iter().all(|filter| filter.matches(page))
+    }
+}
+
 /// Data to pass to the user as closure arguments
 #[derive(Clone)]
 pub struct HandlerArgs<'a> {
@@ -15,7 +45,9 @@ pub struct HandlerArgs<'a> {
     pub page: &'a Page,
     /// CSS element if available
     pub element: Option<ElementRef<'a>>,
-    /// Reqwest client
+    /// Forms found on the current page
+    pub forms: &'a [Form],
+    /// Reqwest client the crawl uses, it doesn't follow redirects, see the README's upgrading notes
     pub client: Arc<Client>,
 }
 
@@ -24,19 +56,83 @@ pub struct HandlerArgs<'a> {
 pub struct Page {
     /// Url of the current location
     pub url: Url,
+    /// Url the response came from after following redirects
+    pub final_url: Url,
+    /// Redirect chain from `url`, the last hop is unfollowed if the policy stopped it
+    pub redirects: Vec<Redirect>,
+    /// Status of the last response
+    pub status: StatusCode,
+    /// Headers of the last response
+    pub headers: HeaderMap,
     /// Response body as a string
     pub text: String,
     /// Parsed HTML document
     pub doc: Html,
     /// Current crawl depth
     pub depth: usize,
+    /// Page this one was found on, None for the start url
+    pub referrer: Option<Url>,
+    /// noindex, nofollow and noarchive from robots meta tags and headers
+    pub robots: RobotsDirectives,
 }
 
 /// These are the events you can hook into
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub enum HandlerEvent {
     /// Handle all found matches of a CSS selector
-    OnSelector(String),
+    OnSelector(CssSelector),
     /// Handle every page loaded
     OnPage,
 }
+
+/// A CSS selector, compiled once when its handler is added
+#[derive(Clone, Debug)]
+pub struct CssSelector {
+    /// Selector as written
+    pub source: String,
+    /// Compiled selector
+    pub selector: Selector,
+}
+
+impl CssSelector {
+    /// Compile `source`, the error describes why it is invalid
+    pub fn parse(source: &str) -> Result<Self, String> {
+        match Selector::parse(source) {
+            Ok(selector) => Ok(Self {
+                source: source.to_string(),
+                selector,
+            }),
+            Err(err) => Err(format!("{:?} at column {}", err.kind, err.location.column)),
+        }
+    }
+}
+
+impl PartialEq for CssSelector {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for CssSelector {}
+
+impl Hash for CssSelector {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+/// Selectors that failed to compile, returned by `CrawlerBuilder::build`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidSelectors(pub Vec<(String, String)>);
+
+impl fmt::Display for InvalidSelectors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid selectors:")?;
+        for (selector, reason) in self.0.iter() {
+            write!(f, " `{selector}` ({reason})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidSelectors {}
@@ -0,0 +1,60 @@
+use super::form::Form;
+use anyhow::Result;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Parameters and referrers seen for a single endpoint
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Endpoint {
+    /// Query parameter names
+    pub params: BTreeSet<String>,
+    /// Form field names
+    pub form_fields: BTreeSet<String>,
+    /// Methods of forms submitting here
+    pub methods: BTreeSet<String>,
+    /// Pages linking or submitting here
+    pub referrers: BTreeSet<String>,
+}
+
+/// Endpoints discovered during a crawl, keyed by url without query or fragment
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Inventory {
+    pub endpoints: BTreeMap<String, Endpoint>,
+}
+
+impl Inventory {
+    /// Record a link from `referrer` to `url`
+    pub fn add_link(&mut self, referrer: &Url, url: &Url) {
+        let endpoint = self.endpoint(url);
+        endpoint
+            .params
+            .extend(url.query_pairs().map(|(name, _)| name.into_owned()));
+        endpoint.referrers.insert(referrer.to_string());
+    }
+
+    /// Record a form found on `referrer`
+    pub fn add_form(&mut self, referrer: &Url, form: &Form) {
+        let endpoint = self.endpoint(&form.action);
+        endpoint
+            .params
+            .extend(form.action.query_pairs().map(|(name, _)| name.into_owned()));
+        endpoint
+            .form_fields
+            .extend(form.inputs.iter().map(|input| input.name.clone()));
+        endpoint.methods.insert(form.method.to_string());
+        endpoint.referrers.insert(referrer.to_string());
+    }
+
+    /// Serialize as pretty printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.endpoints)?)
+    }
+
+    fn endpoint(&mut self, url: &Url) -> &mut Endpoint {
+        let mut key = url.clone();
+        key.set_query(None);
+        key.set_fragment(None);
+        self.endpoints.entry(key.to_string()).or_default()
+    }
+}
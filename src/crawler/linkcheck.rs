@@ -0,0 +1,148 @@
+use crate::auxiliary::xml_escape;
+use anyhow::{bail, Result};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What checking a link found
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct LinkStatus {
+    /// Url of the link
+    pub url: String,
+    /// Status of the last response, None if the request failed or is pending
+    pub status: Option<u16>,
+    /// Where the link redirects to, if it does
+    pub redirect: Option<String>,
+    /// Why the request failed
+    pub error: Option<String>,
+    /// Pages that link here
+    pub referrers: BTreeSet<String>,
+}
+
+impl LinkStatus {
+    /// The request failed or the status is 4xx or 5xx
+    pub fn is_broken(&self) -> bool {
+        self.error.is_some() || self.status.is_some_and(|status| status >= 400)
+    }
+}
+
+/// Status of every link found during a crawl, see `CrawlerBuilder::link_check`
+#[derive(Clone, Debug, Default)]
+pub struct LinkCheck {
+    pub links: BTreeMap<String, LinkStatus>,
+}
+
+impl LinkCheck {
+    fn entry(&mut self, url: &Url) -> &mut LinkStatus {
+        self.links
+            .entry(url.to_string())
+            .or_insert_with(|| LinkStatus {
+                url: url.to_string(),
+                ..LinkStatus::default()
+            })
+    }
+
+    /// Record that `referrer` links to `url`
+    pub(crate) fn refer(&mut self, url: &Url, referrer: &Url) {
+        self.entry(url).referrers.insert(referrer.to_string());
+    }
+
+    /// Record the response to `url`
+    pub(crate) fn record(&mut self, url: &Url, status: u16, redirect: Option<&Url>) {
+        let link = self.entry(url);
+        link.status = Some(status);
+        link.redirect = redirect.map(Url::to_string);
+    }
+
+    /// Record a failed request to `url`
+    pub(crate) fn fail(&mut self, url: &Url, error: &anyhow::Error) {
+        self.entry(url).error = Some(error.root_cause().to_string());
+    }
+
+    /// A response or error was recorded for `url`
+    pub(crate) fn checked(&self, url: &Url) -> bool {
+        self.links
+            .get(url.as_str())
+            .is_some_and(|link| link.status.is_some() || link.error.is_some())
+    }
+
+    /// Broken links, in order
+    pub fn broken(&self) -> impl Iterator<Item = &LinkStatus> {
+        self.links.values().filter(|link| link.is_broken())
+    }
+
+    /// One paragraph per broken link with the pages that reference it, and a count
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for link in self.broken() {
+            let outcome = match (&link.status, &link.error) {
+                (_, Some(error)) => format!("error ({error})"),
+                (Some(status), None) => status.to_string(),
+                (None, None) => String::new(),
+            };
+            text.push_str(&format!("{outcome} {}", link.url));
+            if let Some(redirect) = &link.redirect {
+                text.push_str(&format!(" -> {redirect}"));
+            }
+            text.push('\n');
+            for referrer in link.referrers.iter() {
+                text.push_str(&format!("    referenced by {referrer}\n"));
+            }
+        }
+        let broken = self.broken().count();
+        text.push_str(&format!("{broken} broken of {} links\n", self.links.len()));
+        text
+    }
+
+    /// Broken links as a JSON array
+    pub fn to_json(&self) -> Result<String> {
+        let broken: Vec<&LinkStatus> = self.broken().collect();
+        Ok(serde_json::to_string_pretty(&broken)?)
+    }
+
+    /// JUnit XML with a test case per link, broken links fail
+    pub fn to_junit(&self) -> String {
+        let broken = self.broken().count();
+        let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        text.push_str(&format!(
+            "<testsuite name=\"links\" tests=\"{}\" failures=\"{broken}\">\n",
+            self.links.len()
+        ));
+        for link in self.links.values() {
+            let name = xml_escape(&link.url);
+            if !link.is_broken() {
+                text.push_str(&format!("  <testcase name=\"{name}\"/>\n"));
+                continue;
+            }
+            let message = match (&link.status, &link.error) {
+                (_, Some(error)) => error.clone(),
+                (Some(status), None) => format!("status {status}"),
+                (None, None) => String::new(),
+            };
+            let mut details = vec![];
+            if let Some(redirect) = &link.redirect {
+                details.push(format!("redirects to {redirect}"));
+            }
+            for referrer in link.referrers.iter() {
+                details.push(format!("referenced by {referrer}"));
+            }
+            text.push_str(&format!(
+                "  <testcase name=\"{name}\">\n    <failure message=\"{}\">{}</failure>\n  </testcase>\n",
+                xml_escape(&message),
+                xml_escape(&details.join("\n"))
+            ));
+        }
+        text.push_str("</testsuite>\n");
+        text
+    }
+
+    /// Export by format name: `human`, `json` or `junit`
+    pub fn export(&self, format: &str) -> Result<String> {
+        Ok(match format {
+            "human" => self.to_text(),
+            "json" => self.to_json()?,
+            "junit" => self.to_junit(),
+            other => bail!("unknown report format: {other}"),
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use crate::absolute_url;
+use regex::Regex;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Quoted strings that look like urls or paths
+const QUOTED: &str = r#"["'`]((?:[a-zA-Z][a-zA-Z0-9+.-]*:)?//[^"'`\s<>]+|\.{0,2}/[^"'`\s<>/][^"'`\s<>]*|[a-zA-Z0-9_\-]+/[a-zA-Z0-9_\-/.]*\.(?:php|asp|aspx|jsp|json|action|html|htm|js|txt|xml)(?:\?[^"'`\s<>]*)?)["'`]"#;
+
+/// Targets of fetch(), XMLHttpRequest.open() and common ajax helpers
+const CALLS: &str = r#"(?:fetch|\.open\(\s*["'][A-Za-z]+["']\s*,|\$\.(?:get|post|ajax)|axios(?:\.[a-z]+)?)\(?\s*["'`]([^"'`\s<>]+)["'`]"#;
+
+fn patterns() -> &'static [Regex; 2] {
+    static PATTERNS: OnceLock<[Regex; 2]> = OnceLock::new();
+    PATTERNS.get_or_init(|| [Regex::new(QUOTED).unwrap(), Regex::new(CALLS).unwrap()])
+}
+
+/// Find urls, api paths and request targets in JavaScript source  
+/// Relative paths are resolved against `base_url`
+pub fn extract_js_urls(base_url: &Url, source: &str) -> Vec<Url> {
+    let mut seen = HashSet::new();
+    patterns()
+        .iter()
+        .flat_map(|re| re.captures_iter(source))
+        .filter_map(|cap| cap.get(1))
+        .map(|m| m.as_str())
+        .filter(|link| !link.contains("${") && seen.insert(link.to_string()))
+        .filter_map(|link| absolute_url(base_url, link).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .collect()
+}
@@ -1,16 +1,87 @@
+pub mod auth;
 mod courier;
 pub mod crawler;
 pub mod crawler_builder;
+pub mod event;
+pub mod filter;
+pub mod form;
+pub mod graph;
 pub mod handler;
+pub mod inventory;
+pub mod linkcheck;
+pub mod linkfinder;
+mod pipeline;
+pub mod redirect;
+pub mod robots;
+pub mod sitemap;
+pub mod stats;
 
+pub use auth::*;
 pub use crawler::*;
 pub use crawler_builder::*;
+pub use event::*;
+pub use filter::*;
+pub use form::*;
+pub use graph::*;
 pub use handler::*;
+pub use inventory::*;
+pub use linkcheck::*;
+pub use linkfinder::*;
+pub use redirect::*;
+pub use robots::*;
+pub use sitemap::*;
+pub use stats::*;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `(path, body)` pages on localhost, 404 for anything else, returns the base url
+    /// A body of `-> /path` redirects there
+    async fn serve(pages: &[(&str, &str)]) -> String {
+        serve_logged(pages).await.0
+    }
+
+    /// Like `serve`, also returns the raw requests received
+    async fn serve_logged(pages: &[(&str, &str)]) -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log = requests.clone();
+        let pages: Vec<(String, String)> = pages
+            .iter()
+            .map(|(path, body)| (path.to_string(), body.to_string()))
+            .collect();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                log.lock().unwrap().push(request.clone());
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let (status, body) = match pages.iter().find(|(p, _)| p == path) {
+                    Some((_, body)) => ("200 OK", body.as_str()),
+                    None => ("404 Not Found", ""),
+                };
+                let response = match body.strip_prefix("-> ") {
+                    Some(to) => format!(
+                        "HTTP/1.1 301 Moved Permanently\r\nLocation: {to}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                    None => format!(
+                        "HTTP/1.1 {status}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    ),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (base, requests)
+    }
 
     #[tokio::test]
     async fn crawl_test() {
@@ -52,4 +123,1000 @@ mod tests {
         assert_eq!(visited.len(), 18);
         assert_eq!(links.len(), 61);
     }
+
+    #[test]
+    fn form_test() {
+        let html = r#"
+            <form action="/search" method="post">
+                <input type="hidden" name="csrf" value="token">
+                <input type="text" name="q">
+                <input type="checkbox" name="exact">
+                <select name="sort"><option>new</option><option value="old" selected>Old</option></select>
+                <textarea name="note">hi</textarea>
+                <input type="submit" value="Go">
+            </form>
+        "#;
+        let base = reqwest::Url::parse("https://example.org/page").unwrap();
+        let forms = Form::extract(&scraper::Html::parse_document(html), &base);
+        assert_eq!(forms.len(), 1);
+        assert_eq!(forms[0].action.as_str(), "https://example.org/search");
+        assert_eq!(forms[0].method, reqwest::Method::POST);
+        assert_eq!(forms[0].inputs.len(), 5);
+
+        let mut fill = std::collections::HashMap::new();
+        fill.insert("q".to_string(), "rust".to_string());
+        let fields: Vec<(String, String)> = forms[0].fill(&fill);
+        let pairs: Vec<(&str, &str)> = fields
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("csrf", "token"),
+                ("q", "rust"),
+                ("sort", "old"),
+                ("note", "hi")
+            ]
+        );
+    }
+
+    #[test]
+    fn inventory_test() {
+        let page = reqwest::Url::parse("https://example.org/").unwrap();
+        let link = reqwest::Url::parse("https://example.org/item?id=1&sort=asc#top").unwrap();
+        let html = r#"<form action="/item?tab=1" method="post"><input name="qty"></form>"#;
+        let forms = Form::extract(&scraper::Html::parse_document(html), &page);
+
+        let mut inventory = Inventory::default();
+        inventory.add_link(&page, &link);
+        inventory.add_form(&page, &forms[0]);
+
+        let endpoint = &inventory.endpoints["https://example.org/item"];
+        assert_eq!(endpoint.params.len(), 3);
+        assert!(endpoint.form_fields.contains("qty"));
+        assert!(endpoint.methods.contains("POST"));
+        assert_eq!(endpoint.referrers.len(), 1);
+        assert!(inventory.to_json().unwrap().contains("\"qty\""));
+    }
+
+    #[test]
+    fn linkfinder_test() {
+        let base = reqwest::Url::parse("https://example.org/static/app.js").unwrap();
+        let source = r#"
+            const api = "/api/v1/users";
+            fetch('api/items?page=2');
+            xhr.open("POST", "submit");
+            var cdn = "//cdn.example.org/lib.js";
+            var mime = "application/json";
+            var tpl = `/user/${id}`;
+        "#;
+        let urls: Vec<String> = extract_js_urls(&base, source)
+            .iter()
+            .map(|u| u.to_string())
+            .collect();
+        assert!(urls.contains(&"https://example.org/api/v1/users".to_string()));
+        assert!(urls.contains(&"https://example.org/static/api/items?page=2".to_string()));
+        assert!(urls.contains(&"https://example.org/static/submit".to_string()));
+        assert!(urls.contains(&"https://cdn.example.org/lib.js".to_string()));
+        assert_eq!(urls.len(), 4);
+    }
+
+    #[test]
+    fn form_login_test() {
+        let login = FormLogin::new("https://example.org/session")
+            .unwrap()
+            .form_page("https://example.org/login?next=/")
+            .unwrap()
+            .field("user", "me");
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(login.is_logged_out(&url("https://example.org/login?next=/admin")));
+        assert!(!login.is_logged_out(&url("https://example.org/admin")));
+
+        let login = login.logged_out_when("expired=1");
+        assert!(login.is_logged_out(&url("https://example.org/?expired=1")));
+        assert!(!login.is_logged_out(&url("https://example.org/login")));
+
+        let builder = Crawler::builder().logout_guard();
+        let logout = url("https://example.org/account/logout");
+        assert!(!crate::auxiliary::is_allowed(
+            &logout,
+            &builder.whitelist,
+            &builder.blacklist
+        ));
+    }
+
+    #[test]
+    fn redirect_policy_test() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        let hop = |from: &str, to: &str| Redirect {
+            from: url(from),
+            to: url(to),
+            status: reqwest::StatusCode::FOUND,
+            followed: true,
+        };
+        let local = vec![hop("https://a.org/", "https://a.org/home")];
+        let offsite = vec![
+            hop("https://a.org/", "https://a.org/go"),
+            hop("https://a.org/go", "https://b.org/"),
+        ];
+        let wl = vec!["https://a.org".to_string()];
+
+        assert!(RedirectPolicy::default().follows(&offsite, &[], &[]));
+        assert!(!RedirectPolicy::None.follows(&local, &[], &[]));
+        assert!(!RedirectPolicy::Limited(1).follows(&offsite, &[], &[]));
+        assert!(RedirectPolicy::SameHost(5).follows(&local, &[], &[]));
+        assert!(!RedirectPolicy::SameHost(5).follows(&offsite, &[], &[]));
+        assert!(RedirectPolicy::Scoped(5).follows(&local, &wl, &[]));
+        assert!(!RedirectPolicy::Scoped(5).follows(&offsite, &wl, &[]));
+    }
+
+    #[test]
+    fn filter_test() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("Content-Type", "text/html; charset=utf-8".parse().unwrap());
+        let url = reqwest::Url::parse("https://example.org/blog/post").unwrap();
+        let page = Page {
+            url: url.clone(),
+            final_url: url,
+            redirects: vec![],
+            status: reqwest::StatusCode::OK,
+            headers,
+            text: String::new(),
+            doc: scraper::Html::parse_document(""),
+            depth: 2,
+            referrer: None,
+            robots: RobotsDirectives::default(),
+        };
+
+        assert!(url_matches("/blog/").matches(&page));
+        assert!(!url_matches("/news/").matches(&page));
+        assert!((!url_matches("/news/")).matches(&page));
+        assert!(depth_in(1..=2).matches(&page));
+        assert!(!depth_in(..2).matches(&page));
+        assert!(status_in(200..300).matches(&page));
+        assert!(content_type("TEXT/HTML").matches(&page));
+        assert!(!content_type("json").matches(&page));
+        assert!(url_matches("/news/").or(depth_in(2..)).matches(&page));
+        assert!(!status_in(400..).and(depth_in(2..)).matches(&page));
+        assert!(url_matches("(").error.is_some());
+
+        let builder = Crawler::builder()
+            .add_handler("a", |_| {})
+            .when(url_matches("/blog/"))
+            .add_default_propagators()
+            .when(depth_in(..1));
+        let a = HandlerEvent::OnSelector(CssSelector::parse("a").unwrap());
+        let handler = &builder.handlers[&a][0];
+        assert!(handler.accepts(&page));
+        assert!(builder
+            .propagators
+            .values()
+            .flatten()
+            .all(|prop| prop.filters.len() == 1 && !prop.accepts(&page)));
+
+        assert!(Crawler::builder()
+            .on_page(|_| {})
+            .when(url_matches("["))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn selector_test() {
+        let err = Crawler::builder()
+            .add_handler("a[[", |_| {})
+            .add_handler("div > p", |_| {})
+            .add_propagator("::", |_| vec![])
+            .build()
+            .err()
+            .unwrap();
+        let invalid = err.downcast_ref::<InvalidSelectors>().unwrap();
+        let selectors: Vec<&str> = invalid.0.iter().map(|(sel, _)| sel.as_str()).collect();
+        assert_eq!(selectors, vec!["a[[", "::"]);
+        assert!(Crawler::builder()
+            .add_handler("div > p", |_| {})
+            .build()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn lifecycle_test() {
+        let base = serve(&[
+            (
+                "/",
+                r#"<a href="/a">a</a><a href="/b">b</a><a href="/a">again</a>
+                <a href="https://example.org/">out</a><a href="http://127.0.0.1:1/">dead</a>"#,
+            ),
+            ("/a", r#"<a href="/c">c</a>"#),
+        ])
+        .await;
+
+        let mut events = vec![];
+        let mut summary = None;
+        let mut referrers = vec![];
+        let errs = Crawler::builder()
+            .add_default_propagators()
+            .whitelist("127.0.0.1")
+            .depth(1)
+            .report_depth_skips(true)
+            .on_event(|event| match event {
+                CrawlEvent::Start { url } => events.push(format!("start {}", url.path())),
+                CrawlEvent::Enqueue { url, referrer, .. } => {
+                    events.push(format!("enqueue {} {}", url, referrer.is_some()))
+                }
+                CrawlEvent::Skip { url, reason, .. } => {
+                    events.push(format!("skip {url} {reason:?}"))
+                }
+                CrawlEvent::Request { url, .. } => events.push(format!("request {url}")),
+                CrawlEvent::Response {
+                    url, status, depth, ..
+                } => events.push(format!("response {url} {} {depth}", status.as_u16())),
+                CrawlEvent::FetchError { url, .. } => events.push(format!("error {url}")),
+                CrawlEvent::Finish { .. } => events.push("finish".to_string()),
+            })
+            .on_finish(|s| summary = Some(s.clone()))
+            .on_page(|args| {
+                let referrer = args
+                    .page
+                    .referrer
+                    .as_ref()
+                    .map(|url| url.path().to_string());
+                referrers.push((args.page.url.path().to_string(), referrer));
+            })
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+
+        let has = |event: String| events.contains(&event);
+        assert_eq!(events.first().unwrap(), "start /");
+        assert_eq!(events.last().unwrap(), "finish");
+        assert!(has(format!("enqueue {base}/ false")));
+        assert!(has(format!("enqueue {base}/a true")));
+        assert!(has(format!("request {base}/b")));
+        assert!(has(format!("response {base}/ 200 0")));
+        assert!(has(format!("response {base}/b 404 1")));
+        assert!(has(format!("skip {base}/a Visited")));
+        assert!(has("skip https://example.org/ Scope".to_string()));
+        assert!(has(format!("skip {base}/c Depth")));
+        assert!(has("error http://127.0.0.1:1/".to_string()));
+        assert!(referrers.contains(&("/".to_string(), None)));
+        assert!(referrers.contains(&("/a".to_string(), Some("/".to_string()))));
+
+        let summary = summary.unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(summary.enqueued, 4);
+        assert_eq!(summary.pages, 3);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.skipped, 3);
+        assert_eq!(summary.queued + summary.in_flight, 0);
+        assert_eq!(summary.errors.get("connect"), Some(&1));
+        assert_eq!(summary.statuses.get(&200), Some(&2));
+        assert_eq!(summary.statuses.get(&404), Some(&1));
+        assert_eq!(summary.hosts.get("127.0.0.1"), Some(&3));
+        assert!(summary.bytes > 0 && summary.requests_per_sec() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn graph_test() {
+        let base = serve(&[
+            (
+                "/",
+                r#"<a href="/a">a</a><link rel="next" href="/b"><img src="/logo.png">
+                <a href="https://example.org/?a=1,2">out</a>"#,
+            ),
+            ("/a", r#"<a href="/">home</a><a href="/deep">deep</a>"#),
+        ])
+        .await;
+
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .whitelist("127.0.0.1")
+            .depth(1)
+            .link_graph(true)
+            .build()
+            .unwrap();
+        crawler.crawl(&format!("{base}/")).await.unwrap();
+        let graph = crawler.link_graph().unwrap();
+
+        let edge = |source: &str, target: &str, element: &str, attribute: &str| Edge {
+            source: source.to_string(),
+            target: target.to_string(),
+            element: Some(element.to_string()),
+            attribute: Some(attribute.to_string()),
+        };
+        let root = format!("{base}/");
+        assert!(graph
+            .edges
+            .contains(&edge(&root, &format!("{base}/a"), "a", "href")));
+        assert!(graph
+            .edges
+            .contains(&edge(&root, &format!("{base}/b"), "link", "href")));
+        assert!(graph
+            .edges
+            .contains(&edge(&root, &format!("{base}/logo.png"), "img", "src")));
+        assert!(graph
+            .edges
+            .contains(&edge(&format!("{base}/a"), &root, "a", "href")));
+        // links on the deepest pages and out of scope links are kept
+        assert!(graph.edges.contains(&edge(
+            &format!("{base}/a"),
+            &format!("{base}/deep"),
+            "a",
+            "href"
+        )));
+        assert!(graph.nodes().contains("https://example.org/?a=1,2"));
+
+        let csv = graph.to_csv();
+        assert!(csv.starts_with("source,target,element,attribute\n"));
+        assert!(csv.contains(&format!("{root},\"https://example.org/?a=1,2\",a,href\n")));
+        let dot = graph.export("dot").unwrap();
+        assert!(dot.contains(&format!("\"{root}\" -> \"{base}/a\" [label=\"a[href]\"];")));
+        let graphml = graph.export("graphml").unwrap();
+        assert_eq!(graphml.matches("<edge ").count(), graph.edges.len());
+        assert_eq!(graphml.matches("<node ").count(), graph.nodes().len());
+        let jsonl = graph.export("jsonl").unwrap();
+        assert_eq!(jsonl.lines().count(), graph.edges.len());
+        assert!(jsonl.contains(r#""element":"img","attribute":"src""#));
+        assert!(graph.export("svg").is_err());
+    }
+
+    #[tokio::test]
+    async fn link_check_test() {
+        let other = serve(&[("/ok", r#"<a href="/never">not followed</a>"#)]).await;
+        let base = serve(&[
+            (
+                "/",
+                &format!(
+                    r#"<a href="/a">a</a><a href="/missing">missing</a><a href="/moved">moved</a>
+                    <a href="/old">old</a><a href="{other}/ok">ok</a><a href="{other}/dead">dead</a>
+                    <a href="http://127.0.0.1:1/">refused</a>
+                    <form action="/search"><input name="q"></form>
+                    <form method="post" action="/post"><input name="q"></form>"#
+                ),
+            ),
+            (
+                "/a",
+                r#"<a href="/missing">again</a><a href="/deep">deep</a>"#,
+            ),
+            ("/moved", "-> /a"),
+            ("/old", "-> /gone"),
+        ])
+        .await;
+
+        let mut requests = vec![];
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .whitelist(&format!("{base}/"))
+            .depth(1)
+            .link_check(true)
+            .submit_forms(true)
+            .on_event(|event| {
+                if let CrawlEvent::Request { url, method } = event {
+                    requests.push(format!("{method} {url}"));
+                }
+            })
+            .build()
+            .unwrap();
+        crawler.crawl(&format!("{base}/")).await.unwrap();
+        let check = crawler.link_check().unwrap().clone();
+        drop(crawler);
+        let link = |url: String| check.links.get(&url).unwrap();
+
+        let missing = link(format!("{base}/missing"));
+        assert_eq!(missing.status, Some(404));
+        assert!(missing.referrers.contains(&format!("{base}/")));
+        assert!(missing.referrers.contains(&format!("{base}/a")));
+        let moved = link(format!("{base}/moved"));
+        assert!(!moved.is_broken());
+        assert_eq!(moved.redirect, Some(format!("{base}/a")));
+        let old = link(format!("{base}/old"));
+        assert!(old.is_broken());
+        assert_eq!(old.redirect, Some(format!("{base}/gone")));
+        assert_eq!(link(format!("{base}/deep")).status, Some(404));
+        assert_eq!(link(format!("{other}/ok")).status, Some(200));
+        assert_eq!(link(format!("{other}/dead")).status, Some(404));
+        assert!(link("http://127.0.0.1:1/".to_string()).error.is_some());
+        assert!(!check.links.contains_key(&format!("{other}/never")));
+        assert_eq!(check.broken().count(), 5);
+
+        // links that are not crawled only get a HEAD request
+        let has = |request: String| requests.contains(&request);
+        assert!(has(format!("HEAD {other}/ok")));
+        assert!(has(format!("HEAD {base}/deep")));
+        assert!(has(format!("GET {base}/missing")));
+        assert!(has(format!("POST {base}/post")));
+
+        // form submissions are not links
+        assert!(check
+            .links
+            .keys()
+            .all(|url| !url.contains("/search") && !url.contains("/post")));
+        assert_eq!(requests.len(), check.links.len() + 2);
+
+        let text = check.export("human").unwrap();
+        assert!(text.contains(&format!("404 {base}/old -> {base}/gone\n")));
+        assert!(text.contains(&format!("    referenced by {base}/a\n")));
+        assert!(text.ends_with(&format!("5 broken of {} links\n", check.links.len())));
+        let json: serde_json::Value = serde_json::from_str(&check.export("json").unwrap()).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 5);
+        let junit = check.export("junit").unwrap();
+        assert!(junit.contains(&format!("tests=\"{}\" failures=\"5\"", check.links.len())));
+        assert_eq!(junit.matches("<failure ").count(), 5);
+    }
+
+    #[tokio::test]
+    async fn max_pages_test() {
+        let base = serve(&[
+            (
+                "/",
+                r#"<a href="/a">a</a><a href="/b">b</a><a href="/c">c</a>"#,
+            ),
+            ("/a", ""),
+            ("/b", ""),
+            ("/c", ""),
+        ])
+        .await;
+
+        let mut summary = None;
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .max_pages(2)
+            .on_finish(|s| summary = Some(s.clone()))
+            .build()
+            .unwrap();
+        crawler.crawl(&format!("{base}/")).await.unwrap();
+        assert_eq!(crawler.summary().pages, 2);
+        drop(crawler);
+
+        let summary = summary.unwrap();
+        assert_eq!(summary.enqueued, 4);
+        assert_eq!(summary.pages, 2);
+        assert_eq!(summary.queued, 0);
+        assert_eq!(summary.skipped, 2);
+
+        // the limit is reached with nothing left in flight
+        let mut skipped = vec![];
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .max_pages(1)
+            .on_event(|event| {
+                if let CrawlEvent::Skip { url, reason, .. } = event {
+                    skipped.push((url.path().to_string(), *reason));
+                }
+            })
+            .build()
+            .unwrap();
+        let start = format!("{base}/");
+        let crawl = crawler.crawl(&start);
+        tokio::time::timeout(std::time::Duration::from_secs(5), crawl)
+            .await
+            .expect("crawl hung after max pages")
+            .unwrap();
+        assert_eq!(crawler.summary().pages, 1);
+        assert_eq!(crawler.summary().skipped, 3);
+        drop(crawler);
+        assert_eq!(
+            skipped,
+            ["/a", "/b", "/c"].map(|path| (path.to_string(), SkipReason::MaxPages))
+        );
+    }
+
+    #[tokio::test]
+    async fn parser_test() {
+        use super::courier::{Fetched, Job};
+        use super::pipeline::{run, Closures, Options, Parser};
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        // pages parsed on the pool come back in the order submitted
+        let mut parser = Parser::new(4);
+        for n in 0..16 {
+            let url = reqwest::Url::parse(&format!("http://localhost/{n}")).unwrap();
+            parser.submit(Fetched {
+                job: Job::get(url.clone(), 0),
+                final_url: url,
+                redirects: vec![],
+                status: reqwest::StatusCode::OK,
+                headers: Default::default(),
+                text: "<p>page</p>".repeat(n * 100),
+                elapsed: Duration::ZERO,
+            });
+        }
+        let mut handlers = HashMap::new();
+        let mut propagators = HashMap::new();
+        let mut closures = Closures {
+            handlers: &mut handlers,
+            propagators: &mut propagators,
+            sitemap: None,
+        };
+        let options = Options {
+            propagate_below: 0,
+            link_origins: false,
+        };
+        let client = Arc::new(reqwest::Client::new());
+        let mut paths = vec![];
+        while parser.queued() > 0 {
+            if parser.parsing() {
+                parser.recv().await;
+            }
+            for parsed in parser.take_ready() {
+                let handled = run(&mut closures, &client, parsed, &options);
+                paths.push(handled.url.path().to_string());
+            }
+        }
+        assert_eq!(paths, (0..16).map(|n| format!("/{n}")).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn handler_panic_test() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let base = runtime.block_on(serve(&[
+            (
+                "/",
+                r#"<a href="/a">a</a><a href="/b">b</a><a href="/c">c</a>"#,
+            ),
+            ("/a", ""),
+            ("/b", ""),
+            ("/c", ""),
+        ]));
+
+        // the handler's own panic reaches the caller, not a poisoned lock
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .parse_workers(4)
+            .on_page(|args| {
+                if args.page.url.path() == "/b" {
+                    panic!("handler failed");
+                }
+            })
+            .build()
+            .unwrap();
+        let start = format!("{base}/");
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            runtime.block_on(crawler.crawl(&start))
+        }))
+        .unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"handler failed"));
+    }
+
+    // multi-threaded so pages are handled through `block_in_place`
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sitemap_test() {
+        let other = serve(&[("/", "")]).await.replace("127.0.0.1", "localhost");
+        let base = serve(&[
+            (
+                "/",
+                &format!(
+                    r#"<a href="/a">a</a><a href="/copy">copy</a><a href="/hidden">hidden</a>
+                    <a href="/old">old</a><a href="/missing">missing</a>
+                    <a href="{other}/">other</a><a href="/away">away</a>"#
+                ),
+            ),
+            ("/a", r#"<link rel="canonical" href="/a">"#),
+            ("/copy", r#"<link rel="canonical" href="/a">"#),
+            (
+                "/hidden",
+                r#"<meta name="Robots" content="noarchive, NoIndex">"#,
+            ),
+            ("/old", "-> /new"),
+            ("/new", "new & improved"),
+            ("/away", &format!("-> {other}/")),
+        ])
+        .await;
+
+        let mut crawler = Crawler::builder()
+            .add_default_propagators()
+            .sitemap(true)
+            .build()
+            .unwrap();
+        crawler.crawl(&format!("{base}/")).await.unwrap();
+        let mut sitemap = crawler.sitemap().unwrap().clone();
+
+        // redirects are listed where they landed, copies, noindex, errors and other hosts are left out
+        let urls: Vec<String> = sitemap.urls.keys().cloned().collect();
+        assert_eq!(
+            urls,
+            ["/", "/a", "/new"].map(|path| format!("{base}{path}"))
+        );
+
+        let root = reqwest::Url::parse(&format!("{base}/")).unwrap();
+        let files = sitemap.files(&root).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "sitemap.xml");
+        assert!(files[0]
+            .1
+            .contains(&format!("<url><loc>{base}/a</loc></url>")));
+
+        sitemap.max_urls = 2;
+        sitemap.urls.insert(
+            format!("{base}/?a=1&b=2"),
+            Some("2015-10-21T07:28:00+00:00".into()),
+        );
+        let files = sitemap.files(&root).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["sitemap.xml", "sitemap-1.xml", "sitemap-2.xml"]);
+        assert!(files[0]
+            .1
+            .contains(&format!("<loc>{base}/sitemap-2.xml</loc>")));
+        assert!(files[1].1.contains(&format!(
+            "<loc>{base}/?a=1&amp;b=2</loc><lastmod>2015-10-21T07:28:00+00:00</lastmod>"
+        )));
+        assert_eq!(files[2].1.matches("<url>").count(), 2);
+
+        // a file too big for one more url starts a new one
+        sitemap.max_urls = SITEMAP_MAX_URLS;
+        sitemap.max_bytes = files[1].1.len();
+        assert_eq!(sitemap.urlsets().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn robots_test() {
+        let base = serve(&[
+            (
+                "/",
+                r#"<a href="/a">a</a><a rel="external NoFollow" href="/b">b</a><a href="/c">c</a>"#,
+            ),
+            (
+                "/c",
+                r#"<meta name="robots" content="nofollow, noarchive"><a href="/d">d</a>"#,
+            ),
+            ("/a", ""),
+            ("/b", ""),
+            ("/d", ""),
+        ])
+        .await;
+
+        let crawl = |respect: bool| {
+            let base = base.clone();
+            async move {
+                let mut pages = vec![];
+                let mut skipped = vec![];
+                let mut flags = None;
+                Crawler::builder()
+                    .add_default_propagators()
+                    .respect_robots(respect)
+                    .on_event(|event| {
+                        if let CrawlEvent::Skip { url, reason, .. } = event {
+                            skipped.push((url.path().to_string(), *reason));
+                        }
+                    })
+                    .on_page(|args| {
+                        pages.push(args.page.url.path().to_string());
+                        if args.page.url.path() == "/c" {
+                            flags = Some(args.page.robots);
+                        }
+                    })
+                    .build()
+                    .unwrap()
+                    .crawl(&format!("{base}/"))
+                    .await
+                    .unwrap();
+                pages.sort();
+                (pages, skipped, flags)
+            }
+        };
+
+        let (pages, skipped, flags) = crawl(true).await;
+        assert_eq!(pages, ["/", "/a", "/c"]);
+        assert!(skipped.contains(&("/b".to_string(), SkipReason::Robots)));
+        assert!(skipped.contains(&("/d".to_string(), SkipReason::Robots)));
+        let flags = flags.unwrap();
+        assert!(flags.nofollow && flags.noarchive && !flags.noindex);
+
+        // opt-in, everything is followed by default
+        let (pages, _, _) = crawl(false).await;
+        assert_eq!(pages, ["/", "/a", "/b", "/c", "/d"]);
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.append("X-Robots-Tag", "none".parse().unwrap());
+        headers.append("X-Robots-Tag", "otherbot: noarchive".parse().unwrap());
+        headers.append(
+            "X-Robots-Tag",
+            "unavailable_after: 2030-01-01".parse().unwrap(),
+        );
+        let doc = scraper::Html::parse_document("");
+        let flags = RobotsDirectives::from_response(&headers, &doc);
+        assert!(flags.noindex && flags.nofollow && !flags.noarchive);
+    }
+
+    #[tokio::test]
+    async fn auth_scope_test() {
+        let (other, other_requests) = serve_logged(&[("/", "")]).await;
+        let (base, requests) = serve_logged(&[
+            (
+                "/",
+                &format!(r#"<a href="/a">a</a><a href="{other}/">other</a>"#),
+            ),
+            ("/a", ""),
+        ])
+        .await;
+
+        let has_auth = |requests: &Mutex<Vec<String>>| {
+            let requests = requests.lock().unwrap();
+            !requests.is_empty()
+                && requests
+                    .iter()
+                    .all(|request| request.contains("authorization: Bearer secret"))
+        };
+        let no_auth = |requests: &Mutex<Vec<String>>| {
+            let requests = requests.lock().unwrap();
+            !requests.is_empty()
+                && !requests
+                    .iter()
+                    .any(|request| request.to_lowercase().contains("authorization"))
+        };
+
+        // only the start url's origin gets credentials
+        Crawler::builder()
+            .add_default_propagators()
+            .bearer_auth("secret")
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+        assert!(has_auth(&requests));
+        assert!(no_auth(&other_requests));
+
+        // a host list replaces the start url's origin
+        requests.lock().unwrap().clear();
+        other_requests.lock().unwrap().clear();
+        Crawler::builder()
+            .add_default_propagators()
+            .bearer_auth("secret")
+            .auth_host("localhost")
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+        assert!(no_auth(&requests));
+        assert!(no_auth(&other_requests));
+
+        // form logins go through the courier with the default headers
+        requests.lock().unwrap().clear();
+        Crawler::builder()
+            .header("X-Client", "gar")
+            .unwrap()
+            .form_login(
+                FormLogin::new(&format!("{base}/a"))
+                    .unwrap()
+                    .field("user", "me"),
+            )
+            .depth(0)
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("POST /a") && requests[0].contains("x-client: gar"));
+    }
+
+    #[tokio::test]
+    async fn request_hook_test() {
+        let (base, requests) = serve_logged(&[("/", "")]).await;
+
+        Crawler::builder()
+            .header("X-Remove", "1")
+            .unwrap()
+            .before_request(|request| {
+                let headers = request.headers_mut();
+                headers.remove("x-remove");
+                headers.insert("x-signature", "abc".parse().unwrap());
+            })
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].contains("x-signature: abc"));
+        assert!(!requests[0].contains("x-remove"));
+    }
+
+    #[tokio::test]
+    async fn redirect_credentials_test() {
+        let (other, other_requests) = serve_logged(&[("/", "")]).await;
+        let other = other.replace("127.0.0.1", "localhost");
+        let (port, port_requests) = serve_logged(&[("/", &format!("-> {other}/"))]).await;
+        let (base, requests) =
+            serve_logged(&[("/", "-> /a"), ("/a", &format!("-> {port}/"))]).await;
+
+        Crawler::builder()
+            .auth(Auth::Headers(vec![("X-Api-Key".into(), "key".into())]))
+            .auth_host("127.0.0.1")
+            .auth_host("localhost")
+            .header("Authorization", "Bearer secret")
+            .unwrap()
+            .header("Cookie", "session=abc")
+            .unwrap()
+            .header("X-Client", "gar")
+            .unwrap()
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+
+        // same host hops keep them
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert!(request.contains("x-api-key: key"));
+            assert!(request.contains("authorization: Bearer secret"));
+            assert!(request.contains("cookie: session=abc"));
+        }
+
+        // another port or host only gets the harmless ones
+        for other_requests in [port_requests, other_requests] {
+            let other_requests = other_requests.lock().unwrap();
+            assert_eq!(other_requests.len(), 1);
+            let request = other_requests[0].to_lowercase();
+            assert!(request.contains("x-client: gar"));
+            for name in ["x-api-key", "authorization", "cookie"] {
+                assert!(!request.contains(name), "{name}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn redirect_visited_test() {
+        let (base, requests) = serve_logged(&[
+            ("/", r#"<a href="/old">old</a><a href="/a">a</a>"#),
+            ("/old", "-> /new"),
+            ("/a", r#"<a href="/new">new</a>"#),
+            ("/new", ""),
+        ])
+        .await;
+
+        let mut skipped = vec![];
+        Crawler::builder()
+            .add_default_propagators()
+            .workers(1)
+            .on_event(|event| {
+                if let CrawlEvent::Skip { url, reason, .. } = event {
+                    skipped.push((url.path().to_string(), *reason));
+                }
+            })
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+
+        // the link to where /old landed is not fetched again
+        let new = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.starts_with("GET /new "))
+            .count();
+        assert_eq!(new, 1);
+        assert!(skipped.contains(&("/new".to_string(), SkipReason::Visited)));
+    }
+
+    #[tokio::test]
+    async fn profile_rotation_test() {
+        use crate::profile::{HeaderProfile, Rotation};
+
+        let (base, requests) =
+            serve_logged(&[("/", "-> /a"), ("/a", r#"<a href="/b">b</a>"#), ("/b", "")]).await;
+        Crawler::builder()
+            .add_default_propagators()
+            .header_profile(HeaderProfile::new("one").header("User-Agent", "one"))
+            .header_profile(HeaderProfile::new("two").header("User-Agent", "two"))
+            .profile_rotation(Rotation::PerRequest)
+            .build()
+            .unwrap()
+            .crawl(&format!("{base}/"))
+            .await
+            .unwrap();
+
+        // the redirect is part of the first request, the link is the next one
+        let agents: Vec<(String, String)> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let path = request.split_whitespace().nth(1).unwrap().to_string();
+                let agent = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("user-agent: "))
+                    .unwrap()
+                    .to_string();
+                (path, agent)
+            })
+            .collect();
+        let expected = [("/", "one"), ("/a", "one"), ("/b", "two")];
+        assert_eq!(
+            agents,
+            expected.map(|(path, agent)| (path.to_string(), agent.to_string()))
+        );
+    }
+
+    /// Serve an empty page over TLS with the certificate in `testdata/`, returns the base url
+    async fn serve_tls() -> String {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+        let identity = tokio_native_tls::native_tls::Identity::from_pkcs8(
+            &std::fs::read(format!("{dir}/server.pem")).unwrap(),
+            &std::fs::read(format!("{dir}/server.key")).unwrap(),
+        )
+        .unwrap();
+        let acceptor = tokio_native_tls::native_tls::TlsAcceptor::new(identity).unwrap();
+        let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("https://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = vec![0; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    let response =
+                        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn tls_test() {
+        let base = format!("{}/", serve_tls().await);
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+        let ca = format!("{dir}/ca.pem");
+        let connects = |builder: CrawlerBuilder<'static>| {
+            let base = base.clone();
+            async move {
+                let errors = builder
+                    .depth(0)
+                    .build()
+                    .unwrap()
+                    .crawl(&base)
+                    .await
+                    .unwrap();
+                errors.is_empty()
+            }
+        };
+
+        assert!(!connects(Crawler::builder()).await);
+        assert!(connects(Crawler::builder().danger_accept_invalid_certs(true)).await);
+        assert!(connects(Crawler::builder().root_certificate(&ca).unwrap()).await);
+        let pinned = Crawler::builder()
+            .root_certificate(&ca)
+            .unwrap()
+            .pin_root_certificates(true);
+        assert!(connects(pinned).await);
+        let tls12 = Crawler::builder()
+            .root_certificate(&ca)
+            .unwrap()
+            .min_tls_version(reqwest::tls::Version::TLS_1_2);
+        assert!(connects(tls12).await);
+
+        // client identities load and handshake, the server doesn't ask for them
+        let pem = Crawler::builder()
+            .root_certificate(&ca)
+            .unwrap()
+            .client_identity_pem(&format!("{dir}/client.pem"), &format!("{dir}/client.key"))
+            .unwrap();
+        assert!(connects(pem).await);
+        let pkcs12 = Crawler::builder()
+            .root_certificate(&ca)
+            .unwrap()
+            .client_identity_pkcs12(&format!("{dir}/client.p12"), "secret")
+            .unwrap();
+        assert!(connects(pkcs12).await);
+        // pinning against the system store is in tests/pinned_roots.rs
+    }
 }
@@ -0,0 +1,350 @@
+use super::courier::Fetched;
+use super::form::Form;
+use super::handler::*;
+use super::robots::{is_nofollow, RobotsDirectives};
+use super::sitemap::Sitemap;
+use crate::auxiliary::absolute_url;
+use async_channel::{unbounded, Receiver};
+use reqwest::{Client, Url};
+use scraper::{ElementRef, Html};
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// Handlers and propagators registered with the crawler
+pub struct Closures<'s, 'a> {
+    pub handlers: &'s mut HashMap<HandlerEvent, Vec<Filtered<Handler<'a>>>>,
+    pub propagators: &'s mut HashMap<HandlerEvent, Vec<Filtered<Propagator<'a>>>>,
+    /// Sitemap to add each page to, if enabled
+    pub sitemap: Option<&'s mut Sitemap>,
+}
+
+/// What handling a page produced, for the crawler to queue
+pub struct Handled {
+    /// Url of the page
+    pub url: Url,
+    /// Depth of the page
+    pub depth: usize,
+    /// Forms found on the page
+    pub forms: Vec<Form>,
+    /// Urls returned by propagators, unfiltered
+    pub links: Vec<Link>,
+    /// Robots directives of the page
+    pub robots: RobotsDirectives,
+}
+
+/// A url returned by a propagator and the element that produced it
+pub struct Link {
+    /// Url returned by the propagator
+    pub url: Url,
+    /// Tag name, None for page propagators or when not tracked
+    pub element: Option<String>,
+    /// Attribute whose value resolves to `url`, if any
+    pub attribute: Option<String>,
+    /// The element has `rel="nofollow"`
+    pub nofollow: bool,
+}
+
+/// How to handle parsed pages
+pub struct Options {
+    /// Only run propagators on pages shallower than this
+    pub propagate_below: usize,
+    /// Record which element and attribute produced each link
+    pub link_origins: bool,
+}
+
+/// A parsed page and its forms, ready for the closures
+pub struct Parsed {
+    page: Page,
+    forms: Vec<Form>,
+}
+
+// SAFETY: `Html` is only !Send because its tendrils count references without atomics.
+// Those counts are shared only between tendrils of the same document, which a worker
+// builds and hands over whole without cloning, so no count is touched by two threads.
+unsafe impl Send for Parsed {}
+
+/// Threads that parse pages for the length of a crawl
+struct Pool {
+    jobs: mpsc::Sender<(usize, Fetched)>,
+    parsed: Receiver<(usize, thread::Result<Parsed>)>,
+}
+
+impl Pool {
+    fn new(workers: usize) -> Self {
+        let (jobs, queue) = mpsc::channel::<(usize, Fetched)>();
+        let queue = Arc::new(Mutex::new(queue));
+        let (done, parsed) = unbounded();
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let done = done.clone();
+            // workers exit once the pool is dropped
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                let Ok((index, fetched)) = job else {
+                    break;
+                };
+                let parsed = panic::catch_unwind(AssertUnwindSafe(|| parse(fetched)));
+                if done.send_blocking((index, parsed)).is_err() {
+                    break;
+                }
+            });
+        }
+        Self { jobs, parsed }
+    }
+}
+
+/// Parses pages on a pool of threads, or right away with one worker,
+/// and hands them back in the order they were submitted
+pub struct Parser {
+    pool: Option<Pool>,
+    submitted: usize,
+    next: usize,
+    ready: BTreeMap<usize, Parsed>,
+    /// Pages to parse in `take_ready` without a pool
+    unparsed: Vec<Fetched>,
+}
+
+impl Parser {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            pool: (workers > 1).then(|| Pool::new(workers)),
+            submitted: 0,
+            next: 0,
+            ready: BTreeMap::new(),
+            unparsed: vec![],
+        }
+    }
+
+    /// Queue a page to parse
+    pub fn submit(&mut self, fetched: Fetched) {
+        let index = self.submitted;
+        self.submitted += 1;
+        match &self.pool {
+            Some(pool) => pool.jobs.send((index, fetched)).unwrap(),
+            None => self.unparsed.push(fetched),
+        }
+    }
+
+    /// Pages submitted and not taken yet
+    pub fn queued(&self) -> usize {
+        self.submitted - self.next
+    }
+
+    /// Whether a worker is still parsing a page
+    pub fn parsing(&self) -> bool {
+        self.queued() > self.ready.len() + self.unparsed.len()
+    }
+
+    /// Whether `take_ready` has pages to give
+    pub fn has_ready(&self) -> bool {
+        !self.unparsed.is_empty() || self.ready.contains_key(&self.next)
+    }
+
+    /// Wait for a worker to finish a page, re-raising a panic from parsing it
+    pub async fn recv(&mut self) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        match pool.parsed.recv().await.unwrap() {
+            (index, Ok(parsed)) => {
+                self.ready.insert(index, parsed);
+            }
+            (_, Err(panic)) => panic::resume_unwind(panic),
+        }
+    }
+
+    /// Parsed pages whose turn has come, parsing them here without a pool
+    pub fn take_ready(&mut self) -> Vec<Parsed> {
+        for fetched in self.unparsed.drain(..) {
+            self.ready
+                .insert(self.ready.len() + self.next, parse(fetched));
+        }
+        let mut pages = vec![];
+        while let Some(parsed) = self.ready.remove(&self.next) {
+            pages.push(parsed);
+            self.next += 1;
+        }
+        pages
+    }
+}
+
+/// Run `f` without stalling the runtime's other tasks, when the runtime allows it
+/// Handlers borrow from the caller, so they run on the crawl task instead of `spawn_blocking`
+pub fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(url = %fetched.job.url, bytes = fetched.text.len()))
+)]
+fn parse(fetched: Fetched) -> Parsed {
+    let doc = Html::parse_document(&fetched.text);
+    let forms = Form::extract(&doc, &fetched.job.url);
+    let robots = RobotsDirectives::from_response(&fetched.headers, &doc);
+    let page = Page {
+        url: fetched.job.url,
+        final_url: fetched.final_url,
+        redirects: fetched.redirects,
+        status: fetched.status,
+        headers: fetched.headers,
+        text: fetched.text,
+        doc,
+        depth: fetched.job.depth,
+        referrer: fetched.job.referrer,
+        robots,
+    };
+    Parsed { page, forms }
+}
+
+/// Run the closures on a parsed page
+pub fn run(
+    closures: &mut Closures,
+    client: &Arc<Client>,
+    parsed: Parsed,
+    options: &Options,
+) -> Handled {
+    let Parsed { page, forms } = parsed;
+    do_handlers(closures.handlers, client, &page, &forms);
+    if let Some(sitemap) = closures.sitemap.as_mut() {
+        sitemap.add_page(&page);
+    }
+    let links = if page.depth < options.propagate_below {
+        do_propagators(
+            closures.propagators,
+            client,
+            &page,
+            &forms,
+            options.link_origins,
+        )
+    } else {
+        vec![]
+    };
+    Handled {
+        url: page.url,
+        depth: page.depth,
+        forms,
+        links,
+        robots: page.robots,
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "propagators", skip_all, fields(url = %page.url))
+)]
+fn do_propagators(
+    propagators: &mut HashMap<HandlerEvent, Vec<Filtered<Propagator>>>,
+    client: &Arc<Client>,
+    page: &Page,
+    forms: &[Form],
+    origins: bool,
+) -> Vec<Link> {
+    let mut links = vec![];
+    for (kind, props) in propagators.iter_mut() {
+        let props = props
+            .iter_mut()
+            .filter(|propagator| propagator.accepts(page));
+        match kind {
+            HandlerEvent::OnSelector(css) => {
+                for propagator in props {
+                    page.doc.select(&css.selector).for_each(|el| {
+                        let urls = (propagator.closure)(&HandlerArgs {
+                            page,
+                            element: Some(el),
+                            forms,
+                            client: client.clone(),
+                        });
+                        links.extend(urls.into_iter().map(|url| {
+                            let (element, attribute) = if origins {
+                                origin(&page.url, &el, &url)
+                            } else {
+                                (None, None)
+                            };
+                            Link {
+                                url,
+                                element,
+                                attribute,
+                                nofollow: is_nofollow(&el),
+                            }
+                        }));
+                    });
+                }
+            }
+            HandlerEvent::OnPage => {
+                for propagator in props {
+                    let urls = (propagator.closure)(&HandlerArgs {
+                        page,
+                        element: None,
+                        forms,
+                        client: client.clone(),
+                    });
+                    links.extend(urls.into_iter().map(|url| Link {
+                        url,
+                        element: None,
+                        attribute: None,
+                        nofollow: false,
+                    }));
+                }
+            }
+        }
+    }
+    links
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(name = "handlers", skip_all, fields(url = %page.url))
+)]
+fn do_handlers(
+    handlers: &mut HashMap<HandlerEvent, Vec<Filtered<Handler>>>,
+    client: &Arc<Client>,
+    page: &Page,
+    forms: &[Form],
+) {
+    for (kind, handlers) in handlers.iter_mut() {
+        let handlers = handlers.iter_mut().filter(|handler| handler.accepts(page));
+        match kind {
+            HandlerEvent::OnSelector(css) => {
+                for handler in handlers {
+                    page.doc.select(&css.selector).for_each(|el| {
+                        (handler.closure)(&HandlerArgs {
+                            page,
+                            element: Some(el),
+                            forms,
+                            client: client.clone(),
+                        });
+                    });
+                }
+            }
+            HandlerEvent::OnPage => {
+                for handler in handlers {
+                    (handler.closure)(&HandlerArgs {
+                        page,
+                        element: None,
+                        forms,
+                        client: client.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// tag name of `el` and the attribute that resolves to `url`
+fn origin(base: &Url, el: &ElementRef, url: &Url) -> (Option<String>, Option<String>) {
+    let attribute = el
+        .value()
+        .attrs()
+        .find(|(_, value)| absolute_url(base, value).is_ok_and(|abs| &abs == url))
+        .map(|(name, _)| name.to_string());
+    (Some(el.value().name().to_string()), attribute)
+}
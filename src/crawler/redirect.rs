@@ -0,0 +1,52 @@
+use crate::auxiliary::is_allowed;
+use reqwest::{StatusCode, Url};
+
+/// Which redirects the crawler follows
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RedirectPolicy {
+    /// Never follow redirects, handlers see the 3xx response
+    None,
+    /// Follow up to this many hops
+    Limited(usize),
+    /// Follow up to this many hops while staying on the original host
+    SameHost(usize),
+    /// Follow up to this many hops to urls allowed by the whitelist and blacklist
+    Scoped(usize),
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy::Limited(10)
+    }
+}
+
+impl RedirectPolicy {
+    /// Whether to follow the latest hop of `chain`
+    pub fn follows(&self, chain: &[Redirect], wl: &[String], bl: &[String]) -> bool {
+        let (first, last) = match (chain.first(), chain.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return true,
+        };
+        match *self {
+            RedirectPolicy::None => false,
+            RedirectPolicy::Limited(max) => chain.len() <= max,
+            RedirectPolicy::SameHost(max) => {
+                chain.len() <= max && first.from.host_str() == last.to.host_str()
+            }
+            RedirectPolicy::Scoped(max) => chain.len() <= max && is_allowed(&last.to, wl, bl),
+        }
+    }
+}
+
+/// A single redirect hop
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Redirect {
+    /// Url that responded with a redirect
+    pub from: Url,
+    /// Resolved Location header
+    pub to: Url,
+    /// Redirect status code
+    pub status: StatusCode,
+    /// False if the policy stopped here
+    pub followed: bool,
+}
@@ -0,0 +1,73 @@
+use reqwest::header::HeaderMap;
+use scraper::{ElementRef, Html, Selector};
+
+/// Directives from `<meta name="robots">` and `X-Robots-Tag` headers, `none` sets noindex and nofollow
+/// Header directives for a named user agent, like `otherbot: noindex`, are ignored
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RobotsDirectives {
+    /// Page asks not to be indexed
+    pub noindex: bool,
+    /// Page asks for its links not to be followed
+    pub nofollow: bool,
+    /// Page asks not to be cached or archived
+    pub noarchive: bool,
+}
+
+impl RobotsDirectives {
+    /// Read the directives of a response
+    pub fn from_response(headers: &HeaderMap, doc: &Html) -> Self {
+        let mut directives = Self::default();
+        for value in headers.get_all("x-robots-tag").iter() {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let agent = value
+                .split_once(':')
+                .map(|(agent, _)| agent.trim())
+                .filter(|agent| !agent.contains([',', ' ']) && !is_directive(agent));
+            if agent.is_none() {
+                directives.add(value);
+            }
+        }
+        for meta in doc.select(&Selector::parse("meta[name=robots i][content]").unwrap()) {
+            directives.add(meta.value().attr("content").unwrap());
+        }
+        directives
+    }
+
+    /// Add a comma separated list of directives
+    fn add(&mut self, list: &str) {
+        for directive in list
+            .split(',')
+            .map(|directive| directive.trim().to_lowercase())
+        {
+            match directive.as_str() {
+                "noindex" => self.noindex = true,
+                "nofollow" => self.nofollow = true,
+                "noarchive" => self.noarchive = true,
+                "none" => (self.noindex, self.nofollow) = (true, true),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Directives that take a value after a colon, so are not user agents
+fn is_directive(name: &str) -> bool {
+    [
+        "unavailable_after",
+        "max-snippet",
+        "max-image-preview",
+        "max-video-preview",
+    ]
+    .iter()
+    .any(|directive| name.eq_ignore_ascii_case(directive))
+}
+
+/// `rel` attribute of `el` includes nofollow
+pub fn is_nofollow(el: &ElementRef) -> bool {
+    el.value().attr("rel").is_some_and(|rel| {
+        rel.split_whitespace()
+            .any(|rel| rel.eq_ignore_ascii_case("nofollow"))
+    })
+}
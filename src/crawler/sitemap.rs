@@ -0,0 +1,165 @@
+use super::handler::Page;
+use crate::auxiliary::{absolute_url, xml_escape};
+use anyhow::Result;
+use reqwest::header::{CONTENT_TYPE, LAST_MODIFIED};
+use reqwest::Url;
+use scraper::Selector;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Most urls one sitemap file may list
+pub const SITEMAP_MAX_URLS: usize = 50_000;
+/// Largest one sitemap file may be, uncompressed
+pub const SITEMAP_MAX_BYTES: usize = 50 * 1024 * 1024;
+
+const URLSET_OPEN: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+);
+const URLSET_CLOSE: &str = "</urlset>\n";
+
+/// Pages to list in a sitemap, see `CrawlerBuilder::sitemap`
+#[derive(Clone, Debug)]
+pub struct Sitemap {
+    /// Url of each page and its lastmod, if known
+    pub urls: BTreeMap<String, Option<String>>,
+    /// Start a new file after this many urls ( default: 50,000 )
+    pub max_urls: usize,
+    /// Start a new file before going over this many bytes ( default: 50 MiB )
+    pub max_bytes: usize,
+    /// Only list pages on this host, the crawler sets it to the start url's host
+    pub host: Option<String>,
+}
+
+impl Default for Sitemap {
+    fn default() -> Self {
+        Self {
+            urls: BTreeMap::new(),
+            max_urls: SITEMAP_MAX_URLS,
+            max_bytes: SITEMAP_MAX_BYTES,
+            host: None,
+        }
+    }
+}
+
+impl Sitemap {
+    /// Add `page` if it is a 2xx HTML page on `host` that is its own canonical and not noindex
+    /// Listed under the url it was served from, returns whether it was added
+    pub fn add_page(&mut self, page: &Page) -> bool {
+        if !page.status.is_success() || !is_html(page) || page.robots.noindex {
+            return false;
+        }
+        let host = page.final_url.host_str();
+        if self
+            .host
+            .as_deref()
+            .is_some_and(|allowed| Some(allowed) != host)
+        {
+            return false;
+        }
+        let canonical = page
+            .doc
+            .select(&Selector::parse("link[rel~=canonical][href]").unwrap())
+            .next()
+            .and_then(|link| absolute_url(&page.final_url, link.value().attr("href")?).ok());
+        if canonical.is_some_and(|canonical| canonical != page.final_url) {
+            return false;
+        }
+        let lastmod = page
+            .headers
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(w3c_date);
+        self.urls.insert(page.final_url.to_string(), lastmod);
+        true
+    }
+
+    /// `<urlset>` documents, split to stay under `max_urls` and `max_bytes`
+    pub fn urlsets(&self) -> Vec<String> {
+        let mut sets = vec![];
+        let mut xml = String::from(URLSET_OPEN);
+        let mut count = 0;
+        for (url, lastmod) in self.urls.iter() {
+            let entry = match lastmod {
+                Some(lastmod) => format!(
+                    "  <url><loc>{}</loc><lastmod>{lastmod}</lastmod></url>\n",
+                    xml_escape(url)
+                ),
+                None => format!("  <url><loc>{}</loc></url>\n", xml_escape(url)),
+            };
+            let full = xml.len() + entry.len() + URLSET_CLOSE.len() > self.max_bytes;
+            if count > 0 && (count == self.max_urls || full) {
+                xml.push_str(URLSET_CLOSE);
+                sets.push(std::mem::replace(&mut xml, String::from(URLSET_OPEN)));
+                count = 0;
+            }
+            xml.push_str(&entry);
+            count += 1;
+        }
+        xml.push_str(URLSET_CLOSE);
+        sets.push(xml);
+        sets
+    }
+
+    /// File names and contents, hosted under `base`
+    /// One file is `sitemap.xml`, more are `sitemap-1.xml`, `sitemap-2.xml`, ...
+    /// with `sitemap.xml` as the index
+    pub fn files(&self, base: &Url) -> Result<Vec<(String, String)>> {
+        let sets = self.urlsets();
+        if sets.len() == 1 {
+            return Ok(vec![("sitemap.xml".to_string(), sets[0].clone())]);
+        }
+
+        let mut index = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        ));
+        let mut files = vec![];
+        for (n, set) in sets.into_iter().enumerate() {
+            let name = format!("sitemap-{}.xml", n + 1);
+            let loc = base.join(&name)?;
+            index.push_str(&format!(
+                "  <sitemap><loc>{}</loc></sitemap>\n",
+                xml_escape(loc.as_str())
+            ));
+            files.push((name, set));
+        }
+        index.push_str("</sitemapindex>\n");
+        files.insert(0, ("sitemap.xml".to_string(), index));
+        Ok(files)
+    }
+
+    /// Write `files(base)` into `dir`, returns the paths written
+    pub fn write(&self, dir: impl AsRef<Path>, base: &Url) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for (name, xml) in self.files(base)? {
+            let path = dir.as_ref().join(name);
+            std::fs::write(&path, xml)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+fn is_html(page: &Page) -> bool {
+    page.headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("text/html") || value.contains("xhtml"))
+}
+
+/// HTTP date to a W3C datetime, as sitemaps want
+fn w3c_date(http_date: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // normalize to "Sun, 06 Nov 1994 08:49:37 GMT"
+    let time = httpdate::parse_http_date(http_date).ok()?;
+    let date = httpdate::fmt_http_date(time);
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let month = MONTHS.iter().position(|month| *month == parts[2])? + 1;
+    Some(format!(
+        "{}-{month:02}-{}T{}+00:00",
+        parts[3], parts[1], parts[4]
+    ))
+}
@@ -0,0 +1,79 @@
+use super::event::CrawlSummary;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the latency buckets in `CrawlSummary::latency`, in milliseconds
+pub const LATENCY_BUCKETS_MS: [u64; 11] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Default)]
+struct State {
+    summary: CrawlSummary,
+    started: Option<Instant>,
+}
+
+/// Live counters for a crawl, clone the handle to read them from another task
+#[derive(Clone, Default)]
+pub struct CrawlStats {
+    state: Arc<Mutex<State>>,
+}
+
+impl CrawlStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts so far, or final counts once the crawl is done
+    pub fn snapshot(&self) -> CrawlSummary {
+        let state = self.state.lock().unwrap();
+        let mut summary = state.summary.clone();
+        if let Some(started) = state.started {
+            summary.elapsed = started.elapsed();
+        }
+        summary
+    }
+
+    /// Reset the counters and start the clock
+    pub(crate) fn start(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.summary = CrawlSummary::default();
+        state.started = Some(Instant::now());
+    }
+
+    /// Stop the clock and return the final counts
+    pub(crate) fn finish(&self) -> CrawlSummary {
+        let mut state = self.state.lock().unwrap();
+        if let Some(started) = state.started.take() {
+            state.summary.elapsed = started.elapsed();
+        }
+        state.summary.clone()
+    }
+
+    /// Count a fetch that took `elapsed`
+    pub(crate) fn record_latency(&self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .into_iter()
+            .find(|&bound| ms <= bound)
+            .unwrap_or(u64::MAX);
+        self.update(|stats| {
+            *stats.latency.entry(bucket).or_default() += 1;
+            stats.latency_total += elapsed;
+        });
+    }
+
+    pub(crate) fn update<F: FnOnce(&mut CrawlSummary)>(&self, f: F) {
+        f(&mut self.state.lock().unwrap().summary)
+    }
+}
+
+/// Kind of a fetch error for `CrawlSummary::errors`
+pub(crate) fn error_kind(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(err) if err.is_timeout() => "timeout",
+        Some(err) if err.is_connect() => "connect",
+        Some(err) if err.is_redirect() => "redirect",
+        Some(err) if err.is_body() || err.is_decode() => "body",
+        Some(_) => "request",
+        None => "other",
+    }
+}
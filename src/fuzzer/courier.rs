@@ -0,0 +1,40 @@
+use super::request::FuzzRequest;
+use anyhow::{anyhow, Result};
+use async_channel::*;
+use reqwest::{Client, Request, Response};
+use std::sync::Arc;
+
+/// make a request from the spec and send the results on the async chan
+pub async fn send(
+    spec: FuzzRequest,
+    client: Arc<Client>,
+    sender: Sender<Result<(Request, Response)>>,
+) -> Result<()> {
+    // Must send a message or die trying
+    let built = spec.build(&client).and_then(|req| {
+        // streaming bodies can't be cloned, so build a second copy for handlers
+        let copy = match req.try_clone() {
+            Some(copy) => copy,
+            None => spec.build(&client)?,
+        };
+        Ok((req, copy))
+    });
+
+    match built {
+        Ok((req, copy)) => match client.execute(req).await {
+            Ok(res) => {
+                sender.send(Ok((copy, res))).await.unwrap();
+                Ok(())
+            }
+            Err(err) => {
+                let err = anyhow!(err);
+                sender.send(Err(err)).await.unwrap();
+                Err(anyhow!("Failed request"))
+            }
+        },
+        Err(err) => {
+            sender.send(Err(err)).await.unwrap();
+            Err(anyhow!("Failed build"))
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use super::courier;
 use crate::fuzzer::*;
-use anyhow::{anyhow, Result};
+use anyhow::{Context, Result};
 use async_channel::*;
 use reqwest::{Client, Url};
 use reqwest::{Request, Response};
@@ -29,13 +30,20 @@ impl<'a> Fuzzer<'a> {
         })
     }
 
-    pub async fn fuzz_post<T: ToString>(
+    /// Send all requests in provided iterator, handling responses
+    pub async fn fuzz(
         &mut self,
-        urls: &mut impl Iterator<Item = T>,
-        data: &mut impl Iterator<Item = T>,
+        requests: &mut impl Iterator<Item = FuzzRequest>,
     ) -> Result<Vec<anyhow::Error>> {
-        let mut iter = urls.zip(data);
+        self.try_fuzz(&mut requests.map(Ok)).await
+    }
 
+    /// Send requests from an iterator that may fail to produce them  
+    /// Failed items are returned as errors along with failed requests
+    pub async fn try_fuzz(
+        &mut self,
+        requests: &mut impl Iterator<Item = Result<FuzzRequest>>,
+    ) -> Result<Vec<anyhow::Error>> {
         let mut errors = vec![];
 
         // set up async
@@ -47,33 +55,31 @@ impl<'a> Fuzzer<'a> {
         while !empty || tasks > 0 {
             // Limit the number of concurrent tasks.
             while tasks < s.capacity().unwrap() {
-                // Process URLs in the queue and fetch more pages.
-                match iter.next() {
+                // Process requests in the iterator and send them.
+                match requests.next() {
                     None => {
                         empty = true;
                         break;
                     }
-                    Some((url, data)) => {
-                        if let Ok(url) = Url::parse(&url.to_string()) {
-                            tasks += 1;
-                            tokio::spawn(Self::post_data(
-                                url,
-                                data.to_string(),
-                                self.client.clone(),
-                                s.clone(),
-                            ));
-                        }
+                    Some(Err(err)) => errors.push(err),
+                    Some(Ok(spec)) => {
+                        tasks += 1;
+                        tokio::spawn(courier::send(spec, self.client.clone(), s.clone()));
                     }
                 }
             }
 
+            if tasks == 0 {
+                break;
+            }
+
             // Recieve a message
             let fetched = r.recv().await.unwrap();
             tasks -= 1;
 
             match fetched {
-                Ok((url, res)) => {
-                    self.do_handlers(&url, &res)?;
+                Ok((req, res)) => {
+                    self.do_handlers(&req, &res)?;
                 }
                 Err(err) => {
                     errors.push(err);
@@ -84,54 +90,38 @@ impl<'a> Fuzzer<'a> {
         Ok(errors)
     }
 
+    /// Render `template` with every payload as it is sent
+    /// Templates that fail to parse are returned as errors
+    pub async fn fuzz_template<T: ToString>(
+        &mut self,
+        template: &RequestTemplate,
+        payloads: &mut impl Iterator<Item = T>,
+    ) -> Result<Vec<anyhow::Error>> {
+        let mut requests = payloads.map(|payload| template.render(&payload.to_string()));
+        self.try_fuzz(&mut requests).await
+    }
+
+    /// POST data from the second iterator to urls in the first
+    /// Urls that fail to parse are returned as errors
+    pub async fn fuzz_post<T: ToString>(
+        &mut self,
+        urls: &mut impl Iterator<Item = T>,
+        data: &mut impl Iterator<Item = T>,
+    ) -> Result<Vec<anyhow::Error>> {
+        let mut requests = urls
+            .zip(data)
+            .map(|(url, data)| Ok(FuzzRequest::post(parse_url(url)?, &data.to_string())));
+        self.try_fuzz(&mut requests).await
+    }
+
     /// Request all urls in provided iterator, handling responses
-    /// If a second iterator is given, POST requests will be made
-    /// with data from that iterator
+    /// Urls that fail to parse are returned as errors
     pub async fn fuzz_get<T: ToString>(
         &mut self,
         urls: &mut impl Iterator<Item = T>,
     ) -> Result<Vec<anyhow::Error>> {
-        let mut errors = vec![];
-
-        // set up async
-        let (s, r) = bounded(self.workers);
-        let mut tasks = 0;
-        let mut empty = false;
-
-        // Loop while the queue is not empty or tasks are fetching pages.
-        while !empty || tasks > 0 {
-            // Limit the number of concurrent tasks.
-            while tasks < s.capacity().unwrap() {
-                // Process URLs in the queue and fetch more pages.
-                match urls.next() {
-                    None => {
-                        empty = true;
-                        break;
-                    }
-                    Some(url) => {
-                        if let Ok(url) = Url::parse(&url.to_string()) {
-                            tasks += 1;
-                            tokio::spawn(Self::fetch(url, self.client.clone(), s.clone()));
-                        }
-                    }
-                }
-            }
-
-            // Recieve a message
-            let fetched = r.recv().await.unwrap();
-            tasks -= 1;
-
-            match fetched {
-                Ok((req, res)) => {
-                    self.do_handlers(&req, &res)?;
-                }
-                Err(err) => {
-                    errors.push(err);
-                }
-            }
-        }
-
-        Ok(errors)
+        let mut requests = urls.map(|url| Ok(FuzzRequest::get(parse_url(url)?)));
+        self.try_fuzz(&mut requests).await
     }
 
     fn do_handlers(&mut self, request: &Request, response: &Response) -> Result<()> {
@@ -144,49 +134,9 @@ impl<'a> Fuzzer<'a> {
         }
         Ok(())
     }
+}
 
-    /// make a request and send the results on the async chan
-    async fn fetch(url: Url, client: Arc<Client>, sender: Sender<Result<(Request, Response)>>) {
-        // Must send a message or die trying
-        match client.get(url.clone()).build() {
-            Ok(req) => match client.execute(req.try_clone().unwrap()).await {
-                Ok(res) => {
-                    sender.send(Ok((req, res))).await.unwrap();
-                }
-                Err(err) => {
-                    let err = anyhow!(err);
-                    sender.send(Err(err)).await.unwrap();
-                }
-            },
-            Err(err) => {
-                let err = anyhow!(err);
-                sender.send(Err(err)).await.unwrap();
-            }
-        }
-    }
-
-    /// make a post request and send the results on the async chan
-    async fn post_data(
-        url: Url,
-        data: String,
-        client: Arc<Client>,
-        sender: Sender<Result<(Request, Response)>>,
-    ) {
-        // Must send a message or die trying
-        match client.post(url.clone()).body(data).build() {
-            Ok(req) => match client.execute(req.try_clone().unwrap()).await {
-                Ok(res) => {
-                    sender.send(Ok((req, res))).await.unwrap();
-                }
-                Err(err) => {
-                    let err = anyhow!(err);
-                    sender.send(Err(err)).await.unwrap();
-                }
-            },
-            Err(err) => {
-                let err = anyhow!(err);
-                sender.send(Err(err)).await.unwrap();
-            }
-        }
-    }
+fn parse_url(url: impl ToString) -> Result<Url> {
+    let url = url.to_string();
+    Url::parse(&url).with_context(|| format!("invalid url: {url}"))
 }
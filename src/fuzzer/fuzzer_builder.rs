@@ -1,10 +1,9 @@
 use super::fuzzer::*;
 use super::handler::*;
 
+use crate::proxy::ProxyConfig;
 use anyhow::Result;
 use reqwest::Client;
-use std::fs::File;
-use std::io::Read;
 use std::marker::Send;
 
 pub struct FuzzerBuilder<'a> {
@@ -13,6 +12,12 @@ pub struct FuzzerBuilder<'a> {
     pub workers: usize,
 }
 
+impl<'a> Default for FuzzerBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> FuzzerBuilder<'a> {
     pub fn new() -> Self {
         Self {
@@ -42,15 +47,14 @@ impl<'a> FuzzerBuilder<'a> {
         self
     }
 
-    /// Set an https proxy with a cacert.der file
-    pub fn proxy(mut self, proxy_str: &str, ca_cert: &str) -> Result<Self> {
-        let mut buf = Vec::new();
-        File::open(ca_cert)?.read_to_end(&mut buf)?;
-        let cert = reqwest::Certificate::from_der(&buf)?;
-
-        let proxy = reqwest::Proxy::all(proxy_str)?;
+    /// Set an https proxy with a PEM or DER CA certificate
+    pub fn proxy(self, proxy_str: &str, ca_cert: &str) -> Result<Self> {
+        self.proxy_config(&ProxyConfig::new(proxy_str).ca_cert(ca_cert))
+    }
 
-        self.client_builder = self.client_builder.add_root_certificate(cert).proxy(proxy);
+    /// Use a proxy with optional auth, CA certificate and `NO_PROXY` list
+    pub fn proxy_config(mut self, proxy: &ProxyConfig) -> Result<Self> {
+        self.client_builder = proxy.apply(self.client_builder)?;
         Ok(self)
     }
 
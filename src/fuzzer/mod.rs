@@ -1,14 +1,63 @@
+mod courier;
 pub mod fuzzer;
 pub mod fuzzer_builder;
 pub mod handler;
+pub mod request;
 
 pub use fuzzer::*;
 pub use fuzzer_builder::*;
 pub use handler::*;
+pub use request::*;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answer every request with an empty 200, returns the base url and the raw requests received
+    async fn serve() -> (String, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let log = requests.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let log = log.clone();
+                tokio::spawn(async move {
+                    // read the head, then as much body as it says
+                    let mut request = vec![];
+                    let mut buf = vec![0; 4096];
+                    loop {
+                        let len = stream.read(&mut buf).await.unwrap_or(0);
+                        request.extend_from_slice(&buf[..len]);
+                        let text = String::from_utf8_lossy(&request).to_lowercase();
+                        let Some(head) = text.find("\r\n\r\n") else {
+                            if len == 0 {
+                                break;
+                            }
+                            continue;
+                        };
+                        let length = text[..head]
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .map_or(0, |n| n.trim().parse().unwrap());
+                        if len == 0 || request.len() >= head + 4 + length {
+                            break;
+                        }
+                    }
+                    log.lock().unwrap().push(request);
+                    let response =
+                        "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (base, requests)
+    }
 
     #[tokio::test]
     async fn fuzz_test() {
@@ -31,4 +80,153 @@ mod tests {
         assert_eq!(responses, 3);
         assert_eq!(errs.len(), 1);
     }
+
+    #[test]
+    fn raw_request() {
+        let raw = "POST /login?next=FUZZ HTTP/1.1\r\nHost: example.org\r\nContent-Length: 7\r\nX-Test: 1\r\n\r\nuser=me";
+        let spec = RequestTemplate::new(raw).render("home").unwrap();
+        assert_eq!(spec.method, reqwest::Method::POST);
+        assert_eq!(spec.url.as_str(), "https://example.org/login?next=home");
+        assert_eq!(spec.headers, vec![("X-Test".to_string(), "1".to_string())]);
+        assert_eq!(spec.body, RequestBody::Raw("user=me".to_string()));
+
+        let req = spec
+            .cookie("session", "abc")
+            .build(&reqwest::Client::new())
+            .unwrap();
+        assert_eq!(req.headers()["cookie"], "session=abc");
+    }
+
+    #[test]
+    fn raw_multipart_request() {
+        let body = b"--b\r\nContent-Disposition: form-data; name=\"f\"; filename=\"x.bin\"\r\n\r\n\x89PNG\r\n\x1a\n\x00\xff\r\n--b--\r\n";
+        let mut raw = b"POST /upload?n=FUZZ HTTP/1.1\r\nHost: example.org\r\nContent-Type: multipart/form-data; boundary=b\r\n\r\n".to_vec();
+        raw.extend_from_slice(body);
+
+        let spec = RequestTemplate::new(&raw).render("1").unwrap();
+        assert_eq!(spec.url.as_str(), "https://example.org/upload?n=1");
+        assert_eq!(spec.body, RequestBody::Bytes(body.to_vec()));
+
+        // text bodies keep their CRLFs too
+        let raw = "POST / HTTP/1.1\nHost: example.org\n\n--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--\r\n";
+        let spec = FuzzRequest::from_raw(raw, "https").unwrap();
+        assert_eq!(
+            spec.body,
+            RequestBody::Raw(raw.split_once("\n\n").unwrap().1.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_url_test() {
+        let (base, _) = serve().await;
+        let mut responses = 0;
+        let mut fuzzer = Fuzzer::builder()
+            .add_handler(|_| responses += 1)
+            .build()
+            .unwrap();
+        let urls = ["not a url".to_string(), format!("{base}/a")];
+        let errors = fuzzer.fuzz_get(&mut urls.iter()).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "invalid url: not a url");
+
+        let errors = fuzzer
+            .fuzz_post(
+                &mut urls.clone().into_iter(),
+                &mut ["a=1".into(), "b=2".into()].into_iter(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        drop(fuzzer);
+        assert_eq!(responses, 2);
+    }
+
+    #[tokio::test]
+    async fn template_test() {
+        let (base, requests) = serve().await;
+        let host = base.strip_prefix("http://").unwrap();
+        let template = RequestTemplate::new(format!("GET /FUZZ HTTP/1.1\r\nHost: {host}\r\n\r\n"))
+            .scheme("http");
+
+        // payloads are rendered as they are sent, a bad one is an error like a failed request
+        let rendered = AtomicUsize::new(0);
+        let mut first = None;
+        let mut payloads = (0..50)
+            .map(|n| match n {
+                7 => "bad\nheader".to_string(),
+                n => n.to_string(),
+            })
+            .inspect(|_| {
+                rendered.fetch_add(1, Ordering::Relaxed);
+            });
+        let errors = Fuzzer::builder()
+            .workers(2)
+            .add_handler(|_| {
+                first.get_or_insert(rendered.load(Ordering::Relaxed));
+            })
+            .build()
+            .unwrap()
+            .fuzz_template(&template, &mut payloads)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Invalid header: header HTTP/1.1");
+        assert_eq!(requests.lock().unwrap().len(), 49);
+        assert!(first.unwrap() <= 3);
+    }
+
+    #[tokio::test]
+    async fn multipart_test() {
+        let (base, requests) = serve().await;
+        let url = reqwest::Url::parse(&base).unwrap();
+        let request =
+            FuzzRequest::new(reqwest::Method::POST, url).body(RequestBody::Multipart(vec![
+                ("name".into(), MultipartField::text("FUZZ")),
+                (
+                    "upload".into(),
+                    MultipartField::File {
+                        filename: "x.png".into(),
+                        mime: Some("image/png".into()),
+                        data: b"\x89PNG\r\n\x00\xff".to_vec(),
+                    },
+                ),
+                (
+                    "blob".into(),
+                    MultipartField::File {
+                        filename: "blob".into(),
+                        mime: None,
+                        data: vec![1, 2, 3],
+                    },
+                ),
+            ]));
+        let errors = Fuzzer::builder()
+            .build()
+            .unwrap()
+            .fuzz(&mut std::iter::once(request))
+            .await
+            .unwrap();
+        assert!(errors.is_empty());
+
+        let raw = requests.lock().unwrap().pop().unwrap();
+        let has = |needle: &[u8]| raw.windows(needle.len()).any(|window| window == needle);
+        assert!(has(
+            b"Content-Disposition: form-data; name=\"name\"\r\n\r\nFUZZ\r\n"
+        ));
+        assert!(has(
+            b"Content-Disposition: form-data; name=\"upload\"; filename=\"x.png\"\r\nContent-Type: image/png\r\n\r\n\x89PNG\r\n\x00\xff\r\n"
+        ));
+        assert!(has(
+            b"filename=\"blob\"\r\nContent-Type: application/octet-stream\r\n\r\n\x01\x02\x03\r\n"
+        ));
+    }
+
+    #[test]
+    fn proxy_certificate_test() {
+        // PEM like the crawler, DER was the only format before
+        let ca = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/ca.pem");
+        assert!(Fuzzer::builder().proxy("http://127.0.0.1:8080", ca).is_ok());
+        assert!(Fuzzer::builder()
+            .proxy("http://127.0.0.1:8080", "missing.der")
+            .is_err());
+    }
 }
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, bail, Result};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE, COOKIE};
+use reqwest::multipart;
+use reqwest::{Client, Method, Request, Url};
+use std::fs::read;
+
+/// Body of a FuzzRequest
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum RequestBody {
+    /// No body
+    #[default]
+    Empty,
+    /// Raw body sent as is
+    Raw(String),
+    /// Raw body that is not UTF-8, sent as is
+    Bytes(Vec<u8>),
+    /// Serialized JSON, sent with `Content-Type: application/json`
+    Json(String),
+    /// Urlencoded form fields
+    Form(Vec<(String, String)>),
+    /// Multipart form fields
+    Multipart(Vec<(String, MultipartField)>),
+}
+
+/// Value of a multipart form field
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MultipartField {
+    /// Text value
+    Text(String),
+    /// File upload, `mime` defaults to `application/octet-stream`
+    File {
+        filename: String,
+        mime: Option<String>,
+        data: Vec<u8>,
+    },
+}
+
+impl MultipartField {
+    /// Text value
+    pub fn text(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+
+    /// Read a file to upload under its own file name
+    pub fn file(path: &str, mime: Option<&str>) -> Result<Self> {
+        let filename = std::path::Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(Self::File {
+            filename,
+            mime: mime.map(String::from),
+            data: read(path)?,
+        })
+    }
+}
+
+/// A full request specification, built into a reqwest Request for each send
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FuzzRequest {
+    /// HTTP method
+    pub method: Method,
+    /// Target Url
+    pub url: Url,
+    /// Extra headers, in order
+    pub headers: Vec<(String, String)>,
+    /// Cookies sent in a single `Cookie` header
+    pub cookies: Vec<(String, String)>,
+    /// Request body
+    pub body: RequestBody,
+}
+
+impl FuzzRequest {
+    /// Create a request with no headers or body
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: vec![],
+            cookies: vec![],
+            body: RequestBody::Empty,
+        }
+    }
+
+    /// Create a GET request
+    pub fn get(url: Url) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    /// Create a POST request with a raw body
+    pub fn post(url: Url, data: &str) -> Self {
+        Self::new(Method::POST, url).body(RequestBody::Raw(data.to_string()))
+    }
+
+    /// Parse a raw HTTP request, as saved from Burp
+    /// `scheme` is used with the Host header when the request line has a relative path  
+    /// Line endings are normalized in the head, the body is kept byte for byte
+    pub fn from_raw(raw: impl AsRef<[u8]>, scheme: &str) -> Result<Self> {
+        let (head, body) = split_head(raw.as_ref());
+        let head = std::str::from_utf8(head)?;
+
+        let mut lines = head.lines();
+        let request_line = lines.next().ok_or_else(|| anyhow!("Empty request"))?;
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => bail!("Invalid request line: {request_line}"),
+        };
+        let method = Method::from_bytes(method.as_bytes())?;
+
+        let mut host = None;
+        let mut headers = vec![];
+        for line in lines {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid header: {line}"))?;
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+            } else if !name.eq_ignore_ascii_case("content-length") {
+                headers.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        let url = match Url::parse(target) {
+            Ok(url) => url,
+            Err(_) => match host {
+                Some(host) => Url::parse(&format!("{scheme}://{host}{target}"))?,
+                None => bail!("No Host header for relative target: {target}"),
+            },
+        };
+
+        let body = match String::from_utf8(body.to_vec()) {
+            _ if body.is_empty() => RequestBody::Empty,
+            Ok(text) => RequestBody::Raw(text),
+            Err(_) => RequestBody::Bytes(body.to_vec()),
+        };
+
+        Ok(Self {
+            method,
+            url,
+            headers,
+            cookies: vec![],
+            body,
+        })
+    }
+
+    /// Read and parse a raw HTTP request file
+    pub fn from_file(path: &str, scheme: &str) -> Result<Self> {
+        Self::from_raw(read(path)?, scheme)
+    }
+
+    /// Add a header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Add a cookie
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set the body
+    pub fn body(mut self, body: RequestBody) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Build a reqwest Request with the given client
+    pub fn build(&self, client: &Client) -> Result<Request> {
+        let mut builder = client.request(self.method.clone(), self.url.clone());
+
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<String>>()
+                .join("; ");
+            builder = builder.header(COOKIE, cookies);
+        }
+
+        builder = match &self.body {
+            RequestBody::Empty => builder,
+            RequestBody::Raw(data) => builder.body(data.clone()),
+            RequestBody::Bytes(data) => builder.body(data.clone()),
+            RequestBody::Json(data) => builder
+                .header(CONTENT_TYPE, "application/json")
+                .body(data.clone()),
+            RequestBody::Form(fields) => builder.form(fields),
+            RequestBody::Multipart(fields) => {
+                let mut form = multipart::Form::new();
+                for (name, field) in fields.iter() {
+                    form = match field {
+                        MultipartField::Text(value) => form.text(name.clone(), value.clone()),
+                        MultipartField::File {
+                            filename,
+                            mime,
+                            data,
+                        } => {
+                            let mime = mime.as_deref().unwrap_or("application/octet-stream");
+                            let part = multipart::Part::bytes(data.clone())
+                                .file_name(filename.clone())
+                                .mime_str(mime)?;
+                            form.part(name.clone(), part)
+                        }
+                    };
+                }
+                builder.multipart(form)
+            }
+        };
+
+        Ok(builder.build()?)
+    }
+}
+
+/// A raw HTTP request with a marker to replace with payloads
+#[derive(Clone, Debug)]
+pub struct RequestTemplate {
+    /// Raw request, may hold a binary body
+    pub raw: Vec<u8>,
+    /// Scheme to use with the Host header
+    pub scheme: String,
+    /// Text to replace with each payload ( default: "FUZZ" )
+    pub marker: String,
+}
+
+impl RequestTemplate {
+    /// Create a template from raw request text
+    pub fn new(raw: impl AsRef<[u8]>) -> Self {
+        Self {
+            raw: raw.as_ref().to_vec(),
+            scheme: "https".to_string(),
+            marker: "FUZZ".to_string(),
+        }
+    }
+
+    /// Read a template from a raw HTTP request file
+    pub fn from_file(path: &str) -> Result<Self> {
+        Ok(Self::new(read(path)?))
+    }
+
+    /// Set the scheme ( default: "https" )
+    pub fn scheme(mut self, scheme: &str) -> Self {
+        self.scheme = scheme.to_string();
+        self
+    }
+
+    /// Set the marker ( default: "FUZZ" )
+    pub fn marker(mut self, marker: &str) -> Self {
+        self.marker = marker.to_string();
+        self
+    }
+
+    /// Replace every marker with `payload` and parse the result
+    pub fn render(&self, payload: &str) -> Result<FuzzRequest> {
+        let marker = self.marker.as_bytes();
+        let mut raw = vec![];
+        let mut rest = self.raw.as_slice();
+        while let Some(at) = find(rest, marker).filter(|_| !marker.is_empty()) {
+            raw.extend_from_slice(&rest[..at]);
+            raw.extend_from_slice(payload.as_bytes());
+            rest = &rest[at + marker.len()..];
+        }
+        raw.extend_from_slice(rest);
+        FuzzRequest::from_raw(raw, &self.scheme)
+    }
+}
+
+/// Split a raw request at the first blank line, `\r\n\r\n` or `\n\n`
+fn split_head(raw: &[u8]) -> (&[u8], &[u8]) {
+    let crlf = find(raw, b"\r\n\r\n").map(|at| (at, 4));
+    let lf = find(raw, b"\n\n").map(|at| (at, 2));
+    let blank = match (crlf, lf) {
+        (Some(crlf), Some(lf)) => Some(if crlf.0 < lf.0 { crlf } else { lf }),
+        (crlf, lf) => crlf.or(lf),
+    };
+    match blank {
+        Some((at, len)) => (&raw[..at], &raw[at + len..]),
+        None => (raw, &[]),
+    }
+}
+
+/// Index of the first `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len().max(1))
+        .position(|window| window == needle)
+}
@@ -1,6 +1,11 @@
 pub mod auxiliary;
+pub mod cookies;
 pub mod crawler;
-// pub mod fuzzer;
+pub mod fuzzer;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod profile;
+pub mod proxy;
 
 pub use auxiliary::absolute_url;
 
@@ -27,4 +32,172 @@ mod tests {
         let abs_url = absolute_url(&base_url, href).unwrap();
         assert_eq!(abs_url.as_str(), "https://google.com/");
     }
+
+    #[test]
+    fn cookie_jar() {
+        use crate::cookies::*;
+        use reqwest::cookie::CookieStore;
+        use reqwest::header::HeaderValue;
+
+        let url = Url::parse("https://app.example.org/account/login").unwrap();
+        let jar = CookieJar::from_netscape(
+            "# Netscape HTTP Cookie File\n.example.org\tTRUE\t/\tTRUE\t0\ttheme\tdark\n",
+        )
+        .unwrap();
+        let set = HeaderValue::from_static("session=abc; Path=/account; HttpOnly");
+        jar.set_cookies(&mut [&set].into_iter(), &url);
+
+        let header = jar.cookies(&url).unwrap();
+        assert_eq!(header.to_str().unwrap(), "theme=dark; session=abc");
+        let other = Url::parse("http://app.example.org/").unwrap();
+        assert!(jar.cookies(&other).is_none());
+
+        let saved = CookieJar::from_netscape(&jar.to_netscape()).unwrap();
+        assert_eq!(saved.all(), jar.all());
+
+        assert!(saved.to_netscape().contains("#HttpOnly_app.example.org\t"));
+
+        // paths match on a `/` boundary
+        let header = |path: &str| jar.cookies(&url.join(path).unwrap()).unwrap();
+        assert_eq!(header("/accounts"), "theme=dark");
+        assert_eq!(header("/account/x"), "theme=dark; session=abc");
+
+        // only the host or a parent that isn't a public suffix
+        for domain in ["example.com", "co.uk", "org", "other.example.org"] {
+            let header = format!("toss=1; Domain={domain}");
+            assert!(Cookie::parse_set_cookie(&header, &url).is_err(), "{domain}");
+        }
+        let parent = Cookie::parse_set_cookie("ok=1; Domain=.Example.org", &url).unwrap();
+        assert!(parent.include_subdomains && parent.domain == "example.org");
+
+        let expire = HeaderValue::from_static("session=; Path=/account; Max-Age=0");
+        jar.set_cookies(&mut [&expire].into_iter(), &url);
+        assert_eq!(jar.all().len(), 1);
+
+        // a cookie file adds to cookies already set
+        let path = std::env::temp_dir().join("gar-crawl-cookie-test.txt");
+        jar.save(path.to_str().unwrap()).unwrap();
+        let builder = crate::crawler::Crawler::builder()
+            .cookie_header("https://example.org/", "a=1")
+            .unwrap()
+            .cookie_file(path.to_str().unwrap())
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+        let names: Vec<String> = builder
+            .cookie_jar
+            .unwrap()
+            .all()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, ["a", "theme"]);
+    }
+
+    #[test]
+    fn proxy_pool() {
+        use crate::proxy::*;
+        use std::sync::Arc;
+
+        let client = Arc::new(reqwest::Client::new());
+        let pool = ProxyPool::new(
+            vec![
+                ("socks5://a:1080".into(), client.clone()),
+                ("http://b:8080".into(), client),
+            ],
+            2,
+        )
+        .cooldown(std::time::Duration::from_millis(50));
+
+        let (first, _) = pool.pick();
+        let (second, _) = pool.pick();
+        assert_ne!(first, second);
+
+        pool.report(0, false);
+        pool.report(0, false);
+        assert!((0..4).all(|_| pool.pick().0 == 1));
+
+        let health = pool.health();
+        assert!(!health[0].healthy);
+        assert_eq!(health[0].failures, 2);
+        assert_eq!(health[1].requests, 5);
+
+        // after the cooldown it gets another chance and recovers if it works
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        let picked: Vec<usize> = (0..2).map(|_| pool.pick().0).collect();
+        assert!(picked.contains(&0));
+        pool.report(0, true);
+        assert!(pool.health()[0].healthy);
+
+        let config = ProxyConfig::new("socks5h://127.0.0.1:9050")
+            .auth("user", "pass")
+            .no_proxy("localhost,.internal");
+        assert!(config.apply(reqwest::Client::builder()).is_ok());
+    }
+
+    #[test]
+    fn profile_pool() {
+        use crate::profile::*;
+
+        let profiles = vec![HeaderProfile::chrome(), HeaderProfile::firefox()];
+        let a = Url::parse("https://a.example.org/").unwrap();
+        let b = Url::parse("https://b.example.org/").unwrap();
+
+        let pool = ProfilePool::new(profiles.clone(), Rotation::PerRequest);
+        assert_eq!(pool.pick(&a).unwrap().name, "chrome");
+        assert_eq!(pool.pick(&a).unwrap().name, "firefox");
+
+        let pool = ProfilePool::new(profiles, Rotation::PerHost);
+        assert_eq!(pool.pick(&a).unwrap().name, "chrome");
+        assert_eq!(pool.pick(&b).unwrap().name, "firefox");
+        assert_eq!(pool.pick(&a).unwrap().name, "chrome");
+
+        let site = |url: &Url, referrer: Option<&Url>| {
+            let chrome = HeaderProfile::chrome();
+            let headers: Vec<(&str, &str)> = chrome.headers_for(url, referrer).collect();
+            let (_, site) = headers
+                .iter()
+                .find(|(name, _)| *name == "Sec-Fetch-Site")
+                .unwrap();
+            site.to_string()
+        };
+        assert_eq!(site(&a, None), "none");
+        assert_eq!(site(&a.join("/next").unwrap(), Some(&a)), "same-origin");
+        assert_eq!(site(&b, Some(&a)), "cross-site");
+
+        let bot = HeaderProfile::bot("gar-crawl/0.1", "https://example.org/bot");
+        assert_eq!(
+            bot.user_agent(),
+            Some("gar-crawl/0.1 (+https://example.org/bot)")
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_text() {
+        use crate::crawler::CrawlSummary;
+        use std::time::Duration;
+
+        let mut summary = CrawlSummary {
+            queued: 3,
+            latency_total: Duration::from_millis(1500),
+            ..Default::default()
+        };
+        summary.statuses.insert(200, 4);
+        summary.errors.insert("timeout".to_string(), 1);
+        summary.latency.insert(10, 2);
+        summary.latency.insert(1000, 2);
+        summary.latency.insert(u64::MAX, 1);
+
+        let text = crate::metrics::render(&summary);
+        assert!(text.contains("# TYPE gar_crawl_responses_total counter"));
+        assert!(text.contains("gar_crawl_responses_total{status=\"200\"} 4"));
+        assert!(text.contains("gar_crawl_errors_total{kind=\"timeout\"} 1"));
+        assert!(text.contains("gar_crawl_queue_depth 3"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_bucket{le=\"0.005\"} 0"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_bucket{le=\"0.01\"} 2"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_bucket{le=\"1\"} 4"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_bucket{le=\"+Inf\"} 5"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_sum 1.5"));
+        assert!(text.contains("gar_crawl_request_duration_seconds_count 5"));
+    }
 }
@@ -0,0 +1,125 @@
+use crate::crawler::{CrawlStats, CrawlSummary, LATENCY_BUCKETS_MS};
+use anyhow::Result;
+use std::fmt::Write;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Format crawl counters in the Prometheus text exposition format
+pub fn render(summary: &CrawlSummary) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(text, "# HELP gar_crawl_{name} {help}");
+        let _ = writeln!(text, "# TYPE gar_crawl_{name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(text, "gar_crawl_{name}{labels} {value}");
+        }
+    };
+    let plain = |value: usize| vec![(String::new(), value.to_string())];
+
+    metric(
+        "responses_total",
+        "counter",
+        "Responses by status code",
+        summary
+            .statuses
+            .iter()
+            .map(|(code, n)| (format!("{{status=\"{code}\"}}"), n.to_string()))
+            .collect(),
+    );
+    metric(
+        "errors_total",
+        "counter",
+        "Failed fetches by kind",
+        summary
+            .errors
+            .iter()
+            .map(|(kind, n)| (format!("{{kind=\"{kind}\"}}"), n.to_string()))
+            .collect(),
+    );
+    metric(
+        "pages_total",
+        "counter",
+        "Pages handled",
+        plain(summary.pages),
+    );
+    metric(
+        "bytes_total",
+        "counter",
+        "Response body bytes",
+        plain(summary.bytes),
+    );
+    metric(
+        "skipped_total",
+        "counter",
+        "Urls not queued",
+        plain(summary.skipped),
+    );
+    metric(
+        "queue_depth",
+        "gauge",
+        "Urls waiting in the queue",
+        plain(summary.queued),
+    );
+    metric(
+        "in_flight",
+        "gauge",
+        "Requests being fetched",
+        plain(summary.in_flight),
+    );
+
+    // buckets are cumulative in Prometheus
+    let mut count = 0;
+    let mut buckets = vec![];
+    for bound in LATENCY_BUCKETS_MS.into_iter().chain([u64::MAX]) {
+        count += summary.latency.get(&bound).unwrap_or(&0);
+        let le = match bound {
+            u64::MAX => "+Inf".to_string(),
+            ms => (ms as f64 / 1000.0).to_string(),
+        };
+        buckets.push((format!("_bucket{{le=\"{le}\"}}"), count.to_string()));
+    }
+    let sum = summary.latency_total.as_secs_f64().to_string();
+    buckets.push(("_sum".to_string(), sum));
+    buckets.push(("_count".to_string(), count.to_string()));
+    metric(
+        "request_duration_seconds",
+        "histogram",
+        "Time to fetch a url, including redirects",
+        buckets,
+    );
+
+    text
+}
+
+/// Serve `stats` as Prometheus metrics over HTTP on `addr`, such as "127.0.0.1:9898"
+/// Every path answers with the metrics, stop serving by aborting the returned task  
+/// Failed accepts don't stop the server, they are logged with the `tracing` feature
+pub async fn serve(addr: &str, stats: CrawlStats) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await?;
+    Ok(tokio::spawn(async move {
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_err, "metrics: accept failed");
+                    // errors like running out of file descriptors last a while
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let body = render(&stats.snapshot());
+            tokio::spawn(async move {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    }))
+}
@@ -0,0 +1,169 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const SEC_FETCH: [(&str, &str); 5] = [
+    ("Sec-Fetch-Dest", "document"),
+    ("Sec-Fetch-Mode", "navigate"),
+    ("Sec-Fetch-Site", "none"),
+    ("Sec-Fetch-User", "?1"),
+    ("Upgrade-Insecure-Requests", "1"),
+];
+
+/// A set of headers sent together, like those of a real browser
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeaderProfile {
+    /// Name for reference
+    pub name: String,
+    /// Headers in order, including User-Agent
+    pub headers: Vec<(String, String)>,
+}
+
+impl HeaderProfile {
+    /// Create an empty profile
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            headers: vec![],
+        }
+    }
+
+    /// Add a header
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_sec_fetch(self) -> Self {
+        SEC_FETCH
+            .iter()
+            .fold(self, |profile, (name, value)| profile.header(name, value))
+    }
+
+    /// Chrome on Windows
+    pub fn chrome() -> Self {
+        Self::new("chrome")
+            .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+            .header("Accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7")
+            .header("Accept-Language", "en-US,en;q=0.9")
+            .header("Sec-Ch-Ua", "\"Not_A Brand\";v=\"8\", \"Chromium\";v=\"120\", \"Google Chrome\";v=\"120\"")
+            .header("Sec-Ch-Ua-Mobile", "?0")
+            .header("Sec-Ch-Ua-Platform", "\"Windows\"")
+            .with_sec_fetch()
+    }
+
+    /// Firefox on Linux
+    pub fn firefox() -> Self {
+        Self::new("firefox")
+            .header(
+                "User-Agent",
+                "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0",
+            )
+            .header(
+                "Accept",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8",
+            )
+            .header("Accept-Language", "en-US,en;q=0.5")
+            .with_sec_fetch()
+    }
+
+    /// Safari on macOS
+    pub fn safari() -> Self {
+        Self::new("safari")
+            .header("User-Agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.2 Safari/605.1.15")
+            .header(
+                "Accept",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            )
+            .header("Accept-Language", "en-US,en;q=0.9")
+            .with_sec_fetch()
+    }
+
+    /// Identify honestly as a bot, with a url site owners can visit to learn about it
+    pub fn bot(name: &str, contact_url: &str) -> Self {
+        Self::new("bot")
+            .header("User-Agent", &format!("{name} (+{contact_url})"))
+            .header(
+                "Accept",
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+            )
+    }
+
+    /// Headers for a request to `url` found on `referrer`, with `Sec-Fetch-Site` set to match  
+    /// `none` without a referrer, like a typed url, otherwise `same-origin` or `cross-site`
+    pub fn headers_for<'p>(
+        &'p self,
+        url: &Url,
+        referrer: Option<&Url>,
+    ) -> impl Iterator<Item = (&'p str, &'p str)> {
+        let site = match referrer {
+            None => "none",
+            Some(referrer) if referrer.origin() == url.origin() => "same-origin",
+            Some(_) => "cross-site",
+        };
+        self.headers.iter().map(move |(name, value)| {
+            if name.eq_ignore_ascii_case("sec-fetch-site") {
+                (name.as_str(), site)
+            } else {
+                (name.as_str(), value.as_str())
+            }
+        })
+    }
+
+    /// The User-Agent header, if set
+    pub fn user_agent(&self) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("user-agent"))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// When to switch profiles
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Rotation {
+    /// Use the next profile for every page, its redirects and retries keep it
+    #[default]
+    PerRequest,
+    /// Keep one profile per host
+    PerHost,
+}
+
+/// Profiles rotated while crawling
+#[derive(Debug)]
+pub struct ProfilePool {
+    profiles: Vec<HeaderProfile>,
+    rotation: Rotation,
+    next: AtomicUsize,
+    hosts: Mutex<HashMap<String, usize>>,
+}
+
+impl ProfilePool {
+    pub fn new(profiles: Vec<HeaderProfile>, rotation: Rotation) -> Self {
+        Self {
+            profiles,
+            rotation,
+            next: AtomicUsize::new(0),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Profile to use for a request to `url`, None if the pool is empty
+    pub fn pick(&self, url: &Url) -> Option<&HeaderProfile> {
+        if self.profiles.is_empty() {
+            return None;
+        }
+        let next = || self.next.fetch_add(1, Ordering::Relaxed) % self.profiles.len();
+        let index = match self.rotation {
+            Rotation::PerRequest => next(),
+            Rotation::PerHost => *self
+                .hosts
+                .lock()
+                .unwrap()
+                .entry(url.host_str().unwrap_or_default().to_string())
+                .or_insert_with(next),
+        };
+        self.profiles.get(index)
+    }
+}
@@ -0,0 +1,185 @@
+use crate::auxiliary::load_certificate;
+use anyhow::Result;
+use reqwest::{Client, ClientBuilder, NoProxy, Proxy};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Consecutive failures before a proxy is skipped ( default for `ProxyPool` )
+pub const MAX_PROXY_FAILURES: usize = 3;
+
+/// How long a skipped proxy rests before it is tried again ( default for `ProxyPool` )
+pub const PROXY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// A proxy server, use `http://`, `https://`, `socks5://` or `socks5h://` urls
+#[derive(Clone, Debug, Default)]
+pub struct ProxyConfig {
+    /// Proxy url
+    pub url: String,
+    /// Username and password for the proxy
+    pub auth: Option<(String, String)>,
+    /// Root certificate for intercepting proxies, PEM or DER
+    pub ca_cert: Option<String>,
+    /// Hosts to connect to directly, in `NO_PROXY` format
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Authenticate to the proxy
+    pub fn auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Trust the proxy's CA certificate, PEM or DER
+    pub fn ca_cert(mut self, path: &str) -> Self {
+        self.ca_cert = Some(path.to_string());
+        self
+    }
+
+    /// Connect directly to hosts in a comma separated `NO_PROXY` list
+    pub fn no_proxy(mut self, list: &str) -> Self {
+        self.no_proxy = Some(list.to_string());
+        self
+    }
+
+    /// Configure a client to use this proxy
+    pub fn apply(&self, mut client_builder: ClientBuilder) -> Result<ClientBuilder> {
+        let mut proxy = Proxy::all(&self.url)?;
+        if let Some((username, password)) = &self.auth {
+            proxy = proxy.basic_auth(username, password);
+        }
+        if let Some(list) = &self.no_proxy {
+            proxy = proxy.no_proxy(NoProxy::from_string(list));
+        }
+        if let Some(path) = &self.ca_cert {
+            client_builder = client_builder.add_root_certificate(load_certificate(path)?);
+        }
+        Ok(client_builder.proxy(proxy))
+    }
+}
+
+/// Request and failure counts of a proxy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProxyHealth {
+    /// Proxy url, empty for a direct connection
+    pub url: String,
+    /// Requests sent through this proxy
+    pub requests: usize,
+    /// Failed requests
+    pub failures: usize,
+    /// False after too many consecutive failures
+    pub healthy: bool,
+}
+
+struct PoolEntry {
+    url: String,
+    client: Arc<Client>,
+    requests: AtomicUsize,
+    failures: AtomicUsize,
+    consecutive: AtomicUsize,
+    /// Milliseconds after `ProxyPool::created` when an unhealthy proxy may be tried again
+    retry_at: AtomicU64,
+}
+
+/// Clients rotated per request, skipping proxies that keep failing until they cool down
+pub struct ProxyPool {
+    entries: Vec<PoolEntry>,
+    next: AtomicUsize,
+    max_failures: usize,
+    cooldown: Duration,
+    created: Instant,
+}
+
+impl ProxyPool {
+    /// Create a pool of `(proxy url, client)` pairs
+    pub fn new(clients: Vec<(String, Arc<Client>)>, max_failures: usize) -> Self {
+        let entries = clients
+            .into_iter()
+            .map(|(url, client)| PoolEntry {
+                url,
+                client,
+                requests: AtomicUsize::new(0),
+                failures: AtomicUsize::new(0),
+                consecutive: AtomicUsize::new(0),
+                retry_at: AtomicU64::new(0),
+            })
+            .collect();
+        Self {
+            entries,
+            next: AtomicUsize::new(0),
+            max_failures,
+            cooldown: PROXY_COOLDOWN,
+            created: Instant::now(),
+        }
+    }
+
+    /// Try an unhealthy proxy again after this long ( default: 30s )
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Next client in rotation and its index for `report`  
+    /// Unhealthy proxies are skipped until their cooldown ends, unless all of them are unhealthy
+    pub fn pick(&self) -> (usize, Arc<Client>) {
+        let len = self.entries.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&i| self.is_available(&self.entries[i]))
+            .unwrap_or(start % len);
+        let entry = &self.entries[index];
+        entry.requests.fetch_add(1, Ordering::Relaxed);
+        (index, entry.client.clone())
+    }
+
+    /// Record the outcome of a request made with `pick`  
+    /// Only report failures of the proxy itself, not of the target
+    pub fn report(&self, index: usize, ok: bool) {
+        let entry = &self.entries[index];
+        if ok {
+            entry.consecutive.store(0, Ordering::Relaxed);
+        } else {
+            entry.failures.fetch_add(1, Ordering::Relaxed);
+            let consecutive = entry.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+            if consecutive >= self.max_failures {
+                let retry_at = self.now() + self.cooldown.as_millis() as u64;
+                entry.retry_at.store(retry_at, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counts for every proxy in the pool
+    pub fn health(&self) -> Vec<ProxyHealth> {
+        self.entries
+            .iter()
+            .map(|entry| ProxyHealth {
+                url: entry.url.clone(),
+                requests: entry.requests.load(Ordering::Relaxed),
+                failures: entry.failures.load(Ordering::Relaxed),
+                healthy: self.is_healthy(entry),
+            })
+            .collect()
+    }
+
+    fn is_healthy(&self, entry: &PoolEntry) -> bool {
+        entry.consecutive.load(Ordering::Relaxed) < self.max_failures
+    }
+
+    /// Healthy, or rested long enough to try again
+    fn is_available(&self, entry: &PoolEntry) -> bool {
+        self.is_healthy(entry) || self.now() >= entry.retry_at.load(Ordering::Relaxed)
+    }
+
+    fn now(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+}
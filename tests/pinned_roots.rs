@@ -0,0 +1,55 @@
+//! Pinning roots against the system store, in its own binary because
+//! `SSL_CERT_FILE` is process-wide and other tests make TLS connections
+use gar_crawl::crawler::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_native_tls::native_tls;
+
+const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+
+/// Serve empty pages over TLS with the test server certificate
+async fn serve_tls() -> String {
+    let identity = native_tls::Identity::from_pkcs8(
+        &std::fs::read(format!("{DIR}/server.pem")).unwrap(),
+        &std::fs::read(format!("{DIR}/server.key")).unwrap(),
+    )
+    .unwrap();
+    let acceptor =
+        tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("https://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    base
+}
+
+async fn connects(builder: CrawlerBuilder<'static>, url: &str) -> bool {
+    let errors = builder.depth(0).build().unwrap().crawl(url).await.unwrap();
+    errors.is_empty()
+}
+
+#[test]
+fn pin_root_certificates_test() {
+    // make the test CA a built-in root before any thread could read the environment
+    std::env::set_var("SSL_CERT_FILE", format!("{DIR}/ca.pem"));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let url = serve_tls().await;
+        assert!(connects(Crawler::builder(), &url).await);
+        assert!(!connects(Crawler::builder().pin_root_certificates(true), &url).await);
+    });
+}